@@ -10,14 +10,36 @@ pub struct Model {
     pub name: String,
     pub artist: String,
     pub cover_art_url: Option<String>,
+    pub release_title: Option<String>,
+    pub release_date: Option<String>,
+    pub length_ms: Option<i32>,
+    pub recording_mbid: Option<Uuid>,
+    pub release_mbid: Option<Uuid>,
+    pub cover_art_mirrored_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::isrc_metadata_artist_credits::Entity")]
+    IsrcMetadataArtistCredits,
+    #[sea_orm(has_many = "super::isrc_metadata_genres::Entity")]
+    IsrcMetadataGenres,
     #[sea_orm(has_many = "super::isrc_metadata_status::Entity")]
     IsrcMetadataStatus,
 }
 
+impl Related<super::isrc_metadata_artist_credits::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::IsrcMetadataArtistCredits.def()
+    }
+}
+
+impl Related<super::isrc_metadata_genres::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::IsrcMetadataGenres.def()
+    }
+}
+
 impl Related<super::isrc_metadata_status::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::IsrcMetadataStatus.def()
@@ -1,5 +1,6 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.3
 
+use super::sea_orm_active_enums::MetadataState;
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
@@ -8,6 +9,13 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub isrc: String,
     pub updated_at: DateTimeWithTimeZone,
+    pub state: MetadataState,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_retry_at: Option<DateTimeWithTimeZone>,
+    pub manual: bool,
+    pub match_reason: Option<String>,
+    pub pinned_recording_mbid: Option<Uuid>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -18,12 +18,17 @@
 
 pub mod prelude;
 
+pub mod artists;
 pub mod collection_motifs;
 pub mod collections;
 pub mod comment_likes;
 pub mod comments;
 pub mod isrc_metadata;
+pub mod isrc_metadata_artist_credits;
+pub mod isrc_metadata_genres;
 pub mod isrc_metadata_status;
+pub mod isrc_service_lookups;
+pub mod isrc_service_matches;
 pub mod isrc_services;
 pub mod motif_likes;
 pub mod motif_listeners;
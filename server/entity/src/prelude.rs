@@ -1,11 +1,16 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.3
 
+pub use super::artists::Entity as Artists;
 pub use super::collection_motifs::Entity as CollectionMotifs;
 pub use super::collections::Entity as Collections;
 pub use super::comment_likes::Entity as CommentLikes;
 pub use super::comments::Entity as Comments;
 pub use super::isrc_metadata::Entity as IsrcMetadata;
+pub use super::isrc_metadata_artist_credits::Entity as IsrcMetadataArtistCredits;
+pub use super::isrc_metadata_genres::Entity as IsrcMetadataGenres;
 pub use super::isrc_metadata_status::Entity as IsrcMetadataStatus;
+pub use super::isrc_service_lookups::Entity as IsrcServiceLookups;
+pub use super::isrc_service_matches::Entity as IsrcServiceMatches;
 pub use super::isrc_services::Entity as IsrcServices;
 pub use super::motif_likes::Entity as MotifLikes;
 pub use super::motif_listeners::Entity as MotifListeners;
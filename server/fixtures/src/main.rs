@@ -34,6 +34,7 @@ fn make_user(fixture: &user::UserFixture) -> users::ActiveModel {
         email: Set(fixture.email.to_owned()),
         created_at: Default::default(),
         updated_at: Default::default(),
+        is_admin: Default::default(),
     }
 }
 
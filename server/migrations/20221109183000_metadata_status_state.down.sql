@@ -0,0 +1,7 @@
+DROP INDEX isrc_metadata_status_retry_idx;
+ALTER TABLE isrc_metadata_status
+    DROP COLUMN state,
+    DROP COLUMN attempts,
+    DROP COLUMN last_error,
+    DROP COLUMN next_retry_at;
+DROP TYPE metadata_state;
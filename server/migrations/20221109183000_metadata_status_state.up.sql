@@ -0,0 +1,17 @@
+CREATE TYPE metadata_state AS ENUM ('PENDING', 'FETCHED', 'NOT_FOUND', 'FAILED');
+
+ALTER TABLE isrc_metadata_status
+    ADD COLUMN state         metadata_state           NOT NULL DEFAULT 'PENDING',
+    ADD COLUMN attempts      INTEGER                  NOT NULL DEFAULT 0,
+    ADD COLUMN last_error    VARCHAR,
+    ADD COLUMN next_retry_at TIMESTAMP WITH TIME ZONE;
+
+-- Earlier lookups left no trace of their outcome, so retry all that did not yield metadata
+UPDATE isrc_metadata_status
+SET state = 'FETCHED', attempts = 1
+WHERE isrc IN (SELECT isrc FROM isrc_metadata);
+UPDATE isrc_metadata_status
+SET state = 'FAILED', attempts = 1, next_retry_at = now()
+WHERE state = 'PENDING';
+
+CREATE INDEX isrc_metadata_status_retry_idx ON isrc_metadata_status (state, next_retry_at);
@@ -0,0 +1,7 @@
+DROP TABLE isrc_metadata_artist_credits;
+ALTER TABLE isrc_metadata
+    DROP COLUMN release_title,
+    DROP COLUMN release_date,
+    DROP COLUMN length_ms,
+    DROP COLUMN recording_mbid,
+    DROP COLUMN release_mbid;
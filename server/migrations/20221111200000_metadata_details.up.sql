@@ -0,0 +1,22 @@
+ALTER TABLE isrc_metadata
+    ADD COLUMN release_title  VARCHAR,
+    ADD COLUMN release_date   VARCHAR(10), -- MusicBrainz dates may be partial: YYYY, YYYY-MM or YYYY-MM-DD
+    ADD COLUMN length_ms      INTEGER,
+    ADD COLUMN recording_mbid UUID,
+    ADD COLUMN release_mbid   UUID;
+
+CREATE TABLE isrc_metadata_artist_credits
+(
+    isrc        VARCHAR(12) NOT NULL,
+    position    INTEGER     NOT NULL,
+    name        VARCHAR     NOT NULL,
+    join_phrase VARCHAR     NOT NULL DEFAULT '',
+    artist_mbid UUID,
+    PRIMARY KEY (isrc, position),
+    FOREIGN KEY (isrc) REFERENCES isrc_metadata (isrc) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+-- Previously only the first credit was stored
+INSERT INTO isrc_metadata_artist_credits (isrc, position, name)
+SELECT isrc, 0, artist
+FROM isrc_metadata;
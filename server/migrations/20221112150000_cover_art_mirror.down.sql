@@ -0,0 +1,2 @@
+ALTER TABLE isrc_metadata
+    DROP COLUMN cover_art_mirrored_at;
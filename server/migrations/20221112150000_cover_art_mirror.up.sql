@@ -0,0 +1,3 @@
+-- Set once all sizes of cover_art_url are in storage, cleared when the URL changes
+ALTER TABLE isrc_metadata
+    ADD COLUMN cover_art_mirrored_at TIMESTAMP WITH TIME ZONE;
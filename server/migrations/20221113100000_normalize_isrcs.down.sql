@@ -0,0 +1 @@
+-- Original spellings are not kept, normalized ISRCs remain valid
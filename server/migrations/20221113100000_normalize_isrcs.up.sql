@@ -0,0 +1,26 @@
+-- ISRCs used to be stored as entered. Normalize them to upper case without separators, dropping
+-- metadata fetched under the old spelling so that it is fetched again for the normalized one
+DELETE
+FROM isrc_metadata
+WHERE isrc <> upper(regexp_replace(isrc, '[^A-Za-z0-9]', '', 'g'));
+DELETE
+FROM isrc_metadata_status
+WHERE isrc <> upper(regexp_replace(isrc, '[^A-Za-z0-9]', '', 'g'));
+
+-- Several spellings may each have a row for the same service, which would collide once normalized.
+-- Keep one per normalized ISRC and service, preferring a row that is already normalized
+DELETE
+FROM isrc_services s
+    USING isrc_services k
+WHERE upper(regexp_replace(k.isrc, '[^A-Za-z0-9]', '', 'g')) =
+      upper(regexp_replace(s.isrc, '[^A-Za-z0-9]', '', 'g'))
+  AND k.service = s.service
+  AND (k.isrc <> upper(regexp_replace(k.isrc, '[^A-Za-z0-9]', '', 'g')), k.id) <
+      (s.isrc <> upper(regexp_replace(s.isrc, '[^A-Za-z0-9]', '', 'g')), s.id);
+UPDATE isrc_services
+SET isrc = upper(regexp_replace(isrc, '[^A-Za-z0-9]', '', 'g'))
+WHERE isrc <> upper(regexp_replace(isrc, '[^A-Za-z0-9]', '', 'g'));
+
+UPDATE motifs
+SET isrc = upper(regexp_replace(isrc, '[^A-Za-z0-9]', '', 'g'))
+WHERE isrc <> upper(regexp_replace(isrc, '[^A-Za-z0-9]', '', 'g'));
@@ -0,0 +1 @@
+DROP TABLE isrc_service_lookups;
@@ -0,0 +1,10 @@
+-- Catalog searches for service IDs that yielded nothing, so they are not repeated on every run
+CREATE TABLE isrc_service_lookups
+(
+    id           SERIAL                   NOT NULL, -- See isrc_services
+    isrc         VARCHAR(12)              NOT NULL,
+    service      service                  NOT NULL,
+    looked_up_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (id),
+    UNIQUE (isrc, service)
+);
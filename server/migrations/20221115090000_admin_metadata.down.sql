@@ -0,0 +1,5 @@
+ALTER TABLE isrc_metadata_status
+    DROP COLUMN manual;
+
+ALTER TABLE users
+    DROP COLUMN is_admin;
@@ -0,0 +1,6 @@
+ALTER TABLE users
+    ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Metadata set by an admin is not overwritten by periodic refreshes
+ALTER TABLE isrc_metadata_status
+    ADD COLUMN manual BOOLEAN NOT NULL DEFAULT FALSE;
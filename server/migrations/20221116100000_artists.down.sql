@@ -0,0 +1,6 @@
+DROP INDEX isrc_metadata_artist_credits_artist_mbid_idx;
+
+ALTER TABLE isrc_metadata_artist_credits
+    DROP CONSTRAINT isrc_metadata_artist_credits_artist_mbid_fkey;
+
+DROP TABLE artists;
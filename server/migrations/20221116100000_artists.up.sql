@@ -0,0 +1,19 @@
+CREATE TABLE artists
+(
+    mbid       UUID PRIMARY KEY,
+    name       VARCHAR     NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Credited names may differ from the artist's own, but are the best there is until the next refresh
+INSERT INTO artists (mbid, name)
+SELECT DISTINCT ON (artist_mbid) artist_mbid, name
+FROM isrc_metadata_artist_credits
+WHERE artist_mbid IS NOT NULL
+ORDER BY artist_mbid;
+
+ALTER TABLE isrc_metadata_artist_credits
+    ADD CONSTRAINT isrc_metadata_artist_credits_artist_mbid_fkey
+        FOREIGN KEY (artist_mbid) REFERENCES artists (mbid) ON UPDATE CASCADE ON DELETE SET NULL;
+
+CREATE INDEX isrc_metadata_artist_credits_artist_mbid_idx ON isrc_metadata_artist_credits (artist_mbid);
@@ -0,0 +1 @@
+DROP TABLE isrc_metadata_genres;
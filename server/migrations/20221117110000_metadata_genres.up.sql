@@ -0,0 +1,10 @@
+CREATE TABLE isrc_metadata_genres
+(
+    isrc     VARCHAR(12) NOT NULL,
+    genre    VARCHAR     NOT NULL,
+    position INTEGER     NOT NULL,
+    PRIMARY KEY (isrc, genre),
+    FOREIGN KEY (isrc) REFERENCES isrc_metadata (isrc) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX isrc_metadata_genres_genre_idx ON isrc_metadata_genres (genre);
@@ -0,0 +1,3 @@
+ALTER TABLE isrc_metadata_status
+    DROP COLUMN match_reason,
+    DROP COLUMN pinned_recording_mbid;
@@ -0,0 +1,5 @@
+-- An ISRC may map to several MusicBrainz recordings. The reason for the one chosen is kept,
+-- and a recording can be pinned if the choice was wrong
+ALTER TABLE isrc_metadata_status
+    ADD COLUMN match_reason          VARCHAR,
+    ADD COLUMN pinned_recording_mbid UUID;
@@ -0,0 +1,3 @@
+DROP TABLE isrc_service_matches;
+
+DROP TYPE match_state;
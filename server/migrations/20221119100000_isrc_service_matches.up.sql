@@ -0,0 +1,23 @@
+CREATE TYPE match_state AS ENUM ('ACCEPTED', 'FLAGGED', 'REJECTED');
+
+-- Service tracks found by title, artist and duration when a catalog did not know the ISRC.
+-- Only accepted matches are copied to isrc_services, flagged ones wait for review
+CREATE TABLE isrc_service_matches
+(
+    id         SERIAL                   NOT NULL, -- See isrc_services
+    isrc       VARCHAR(12)              NOT NULL,
+    service    service                  NOT NULL,
+    service_id VARCHAR                  NOT NULL,
+    -- The catalog's ISRC for the track, e.g. of a regional reissue
+    track_isrc VARCHAR,
+    name       VARCHAR                  NOT NULL,
+    artist     VARCHAR                  NOT NULL,
+    length_ms  INTEGER,
+    confidence REAL                     NOT NULL,
+    state      match_state              NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (id),
+    UNIQUE (isrc, service, service_id)
+);
+
+CREATE INDEX isrc_service_matches_state_idx ON isrc_service_matches (state);
@@ -0,0 +1,352 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use chrono::{NaiveDate, Utc};
+use itertools::Itertools;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, NotSet,
+    QueryFilter, QueryOrder, TransactionTrait,
+};
+use uuid::Uuid;
+
+use db::util::OptLimitOffset;
+use entity::isrc_metadata_status::{Entity as StatusEntity, Model as StatusModel};
+use entity::isrc_service_matches::{Entity as MatchEntity, Model as MatchModel};
+use entity::sea_orm_active_enums::MatchState as DbMatchState;
+use entity::sea_orm_active_enums::MetadataState as DbMetadataState;
+use entity::{isrc_metadata_status, isrc_service_lookups, isrc_service_matches, isrc_services};
+
+use crate::db;
+use crate::domain::admin::typedef::{
+    MatchState, MetadataInput, MetadataState, MetadataStatus, ServiceMatch,
+};
+use crate::domain::common::typedef::Isrc;
+use crate::metadata::provider::{normalize_genre, ArtistCredit, TrackMetadata};
+use crate::metadata::status::{self, FetchOutcome};
+use crate::metadata::store_metadata;
+use crate::rest::util::{ApiResult, DataError};
+
+impl From<DbMetadataState> for MetadataState {
+    fn from(state: DbMetadataState) -> Self {
+        match state {
+            DbMetadataState::Pending => MetadataState::Pending,
+            DbMetadataState::Fetched => MetadataState::Fetched,
+            DbMetadataState::NotFound => MetadataState::NotFound,
+            DbMetadataState::Failed => MetadataState::Failed,
+        }
+    }
+}
+
+impl From<MetadataState> for DbMetadataState {
+    fn from(state: MetadataState) -> Self {
+        match state {
+            MetadataState::Pending => DbMetadataState::Pending,
+            MetadataState::Fetched => DbMetadataState::Fetched,
+            MetadataState::NotFound => DbMetadataState::NotFound,
+            MetadataState::Failed => DbMetadataState::Failed,
+        }
+    }
+}
+
+impl From<DbMatchState> for MatchState {
+    fn from(state: DbMatchState) -> Self {
+        match state {
+            DbMatchState::Accepted => MatchState::Accepted,
+            DbMatchState::Flagged => MatchState::Flagged,
+            DbMatchState::Rejected => MatchState::Rejected,
+        }
+    }
+}
+
+impl From<MatchState> for DbMatchState {
+    fn from(state: MatchState) -> Self {
+        match state {
+            MatchState::Accepted => DbMatchState::Accepted,
+            MatchState::Flagged => DbMatchState::Flagged,
+            MatchState::Rejected => DbMatchState::Rejected,
+        }
+    }
+}
+
+impl From<MatchModel> for ServiceMatch {
+    fn from(model: MatchModel) -> Self {
+        Self {
+            id: model.id,
+            isrc: model.isrc,
+            service: model.service.into(),
+            service_id: model.service_id,
+            track_isrc: model.track_isrc,
+            name: model.name,
+            artist: model.artist,
+            length_ms: model.length_ms,
+            confidence: model.confidence,
+            state: model.state.into(),
+            created_at: model.created_at.with_timezone(&Utc),
+        }
+    }
+}
+
+impl From<StatusModel> for MetadataStatus {
+    fn from(model: StatusModel) -> Self {
+        Self {
+            isrc: model.isrc,
+            state: model.state.into(),
+            attempts: model.attempts,
+            last_error: model.last_error,
+            updated_at: model.updated_at.with_timezone(&Utc),
+            next_retry_at: model
+                .next_retry_at
+                .map(|next_retry_at| next_retry_at.with_timezone(&Utc)),
+            manual: model.manual,
+            match_reason: model.match_reason,
+            pinned_recording_mbid: model.pinned_recording_mbid,
+        }
+    }
+}
+
+pub async fn get_metadata_statuses(
+    db: &DatabaseConnection,
+    state: Option<MetadataState>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+) -> ApiResult<Vec<MetadataStatus>> {
+    let mut query = StatusEntity::find().order_by_desc(isrc_metadata_status::Column::UpdatedAt);
+    if let Some(state) = state {
+        query = query.filter(isrc_metadata_status::Column::State.eq(DbMetadataState::from(state)));
+    }
+    let models = query.opt_limit_offset(limit, offset).all(db).await?;
+    Ok(models.into_iter().map(|model| model.into()).collect())
+}
+
+pub async fn get_metadata_status(
+    db: &DatabaseConnection,
+    isrc: Isrc,
+) -> ApiResult<Option<MetadataStatus>> {
+    let model = StatusEntity::find_by_id(isrc.into()).one(db).await?;
+    Ok(model.map(|model| model.into()))
+}
+
+// Hands the ISRC back to the fetch jobs, also if it was set by hand before
+pub async fn reset_metadata_status(
+    db: &DatabaseConnection,
+    isrc: Isrc,
+) -> ApiResult<MetadataStatus> {
+    let isrc: String = isrc.into();
+    db.transaction::<_, StatusModel, DbErr>(|txn| {
+        Box::pin(async move {
+            status::mark_pending(txn, isrc.clone()).await?;
+            let mut model = StatusEntity::find_by_id(isrc.clone())
+                .one(txn)
+                .await?
+                .ok_or(DbErr::RecordNotFound(isrc))?
+                .into_active_model();
+            model.manual = Set(false);
+            model.update(txn).await
+        })
+    })
+    .await
+    .map(|model| model.into())
+    .map_err(|err| err.into())
+}
+
+// Re-fetches the ISRC from the pinned MusicBrainz recording, or lets the provider choose again
+pub async fn pin_recording(
+    db: &DatabaseConnection,
+    isrc: Isrc,
+    recording_mbid: Option<Uuid>,
+) -> ApiResult<MetadataStatus> {
+    let isrc: String = isrc.into();
+    db.transaction::<_, StatusModel, DbErr>(|txn| {
+        Box::pin(async move {
+            status::mark_pending(txn, isrc.clone()).await?;
+            let mut model = StatusEntity::find_by_id(isrc.clone())
+                .one(txn)
+                .await?
+                .ok_or(DbErr::RecordNotFound(isrc))?
+                .into_active_model();
+            model.manual = Set(false);
+            model.pinned_recording_mbid = Set(recording_mbid);
+            model.update(txn).await
+        })
+    })
+    .await
+    .map(|model| model.into())
+    .map_err(|err| err.into())
+}
+
+// Release dates are stored as reported by providers, with the precision they know
+fn is_valid_release_date(date: &str) -> bool {
+    let well_formed = date.char_indices().all(|(i, c)| match i {
+        4 | 7 => c == '-',
+        _ => c.is_ascii_digit(),
+    });
+    let full_date = match date.len() {
+        4 => format!("{}-01-01", date),
+        7 => format!("{}-01", date),
+        10 => date.to_owned(),
+        _ => return false,
+    };
+    well_formed && NaiveDate::parse_from_str(&full_date, "%Y-%m-%d").is_ok()
+}
+
+pub async fn set_metadata(
+    db: &DatabaseConnection,
+    isrc: Isrc,
+    input: MetadataInput,
+) -> ApiResult<MetadataStatus> {
+    if let Some(release_date) = &input.release_date {
+        if !is_valid_release_date(release_date) {
+            return Err(DataError::Invalid(format!(
+                "Release date \"{}\" is not YYYY, YYYY-MM or YYYY-MM-DD",
+                release_date
+            ))
+            .into());
+        }
+    }
+    let isrc: String = isrc.into();
+    let track_metadata = TrackMetadata {
+        artist_credits: ArtistCredit::from_names(vec![input.artist.clone()]),
+        name: input.name,
+        artist: input.artist,
+        cover_art_url: input.cover_art_url,
+        release_title: input.release_title,
+        release_date: input.release_date,
+        length_ms: input.length_ms,
+        recording_mbid: None,
+        release_mbid: None,
+        genres: input
+            .genres
+            .iter()
+            .map(|genre| normalize_genre(genre))
+            .unique()
+            .collect(),
+        match_reason: None,
+    };
+    db.transaction::<_, StatusModel, DbErr>(|txn| {
+        Box::pin(async move {
+            status::mark_pending(txn, isrc.clone()).await?;
+            store_metadata(txn, isrc.clone(), track_metadata, None).await?;
+            status::record_outcome(txn, isrc.clone(), FetchOutcome::Fetched).await?;
+            let mut model = StatusEntity::find_by_id(isrc.clone())
+                .one(txn)
+                .await?
+                .ok_or(DbErr::RecordNotFound(isrc))?
+                .into_active_model();
+            model.manual = Set(true);
+            model.update(txn).await
+        })
+    })
+    .await
+    .map(|model| model.into())
+    .map_err(|err| err.into())
+}
+
+pub async fn get_service_matches(
+    db: &DatabaseConnection,
+    state: Option<MatchState>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+) -> ApiResult<Vec<ServiceMatch>> {
+    let mut query = MatchEntity::find().order_by_desc(isrc_service_matches::Column::CreatedAt);
+    if let Some(state) = state {
+        query = query.filter(isrc_service_matches::Column::State.eq(DbMatchState::from(state)));
+    }
+    let models = query.opt_limit_offset(limit, offset).all(db).await?;
+    Ok(models.into_iter().map(|model| model.into()).collect())
+}
+
+// Accepting replaces the ISRC's service ID, rejecting removes it if it came from this match.
+// Rejected matches are not suggested again by the backfill
+pub async fn resolve_service_match(
+    db: &DatabaseConnection,
+    id: i32,
+    accept: bool,
+) -> ApiResult<ServiceMatch> {
+    let model = MatchEntity::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or(DataError::NotFound("Service match not found".to_owned()))?;
+    db.transaction::<_, MatchModel, DbErr>(|txn| {
+        Box::pin(async move {
+            if accept {
+                isrc_services::Entity::insert(isrc_services::ActiveModel {
+                    id: NotSet,
+                    isrc: Set(model.isrc.clone()),
+                    service: Set(model.service.clone()),
+                    service_id: Set(model.service_id.clone()),
+                })
+                .on_conflict(
+                    OnConflict::columns([
+                        isrc_services::Column::Isrc,
+                        isrc_services::Column::Service,
+                    ])
+                    .update_column(isrc_services::Column::ServiceId)
+                    .to_owned(),
+                )
+                .exec(txn)
+                .await?;
+                isrc_service_lookups::Entity::delete_many()
+                    .filter(isrc_service_lookups::Column::Isrc.eq(model.isrc.clone()))
+                    .filter(isrc_service_lookups::Column::Service.eq(model.service.clone()))
+                    .exec(txn)
+                    .await?;
+            } else {
+                isrc_services::Entity::delete_many()
+                    .filter(isrc_services::Column::Isrc.eq(model.isrc.clone()))
+                    .filter(isrc_services::Column::Service.eq(model.service.clone()))
+                    .filter(isrc_services::Column::ServiceId.eq(model.service_id.clone()))
+                    .exec(txn)
+                    .await?;
+            }
+            let mut model = model.into_active_model();
+            model.state = Set(if accept {
+                DbMatchState::Accepted
+            } else {
+                DbMatchState::Rejected
+            });
+            model.update(txn).await
+        })
+    })
+    .await
+    .map(|model| model.into())
+    .map_err(|err| err.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_partial_release_dates() {
+        assert!(is_valid_release_date("2022"));
+        assert!(is_valid_release_date("2022-02"));
+        assert!(is_valid_release_date("2022-02-28"));
+    }
+
+    #[test]
+    fn rejects_malformed_release_dates() {
+        assert!(!is_valid_release_date(""));
+        assert!(!is_valid_release_date("22"));
+        assert!(!is_valid_release_date("2022-2"));
+        assert!(!is_valid_release_date("2022/02/28"));
+        assert!(!is_valid_release_date("+022-02-28"));
+        assert!(!is_valid_release_date("2022-13"));
+        assert!(!is_valid_release_date("2022-02-30"));
+        assert!(!is_valid_release_date("28 February 2022"));
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use apalis::prelude::Storage;
+use apalis::redis::RedisStorage;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::*;
+use async_graphql::{ComplexObject, Context, Object};
+use uuid::Uuid;
+
+use crate::domain::admin::datasource;
+use crate::domain::admin::typedef::{
+    MatchState, MetadataInput, MetadataState, MetadataStatus, ServiceMatch,
+};
+use crate::domain::common::typedef::Isrc;
+use crate::domain::motif::dataloader::MotifMetadataLoader;
+use crate::domain::motif::pubsub::{topic_metadata_updated, topic_service_ids_updated};
+use crate::domain::motif::typedef::Metadata;
+use crate::gql::auth::Admin;
+use crate::gql::connection::{position_page, PositionConnection};
+use crate::gql::util::{CoerceGraphqlError, ConnectionParams, ContextDependencies};
+use crate::metadata::FetchMetadata;
+use crate::pubsub::event::{Event, EventPayload};
+use crate::PubSubHandle;
+
+#[ComplexObject]
+impl MetadataStatus {
+    async fn metadata(&self, ctx: &Context<'_>) -> Result<Option<Metadata>> {
+        let loader: &DataLoader<MotifMetadataLoader> = ctx.require();
+        loader.load_one(self.isrc.clone()).await.coerce_gql_err()
+    }
+}
+
+#[ComplexObject]
+impl ServiceMatch {
+    // The metadata the match was scored against
+    async fn metadata(&self, ctx: &Context<'_>) -> Result<Option<Metadata>> {
+        let loader: &DataLoader<MotifMetadataLoader> = ctx.require();
+        loader.load_one(self.isrc.clone()).await.coerce_gql_err()
+    }
+}
+
+#[derive(Default)]
+pub struct AdminQuery;
+
+#[Object]
+impl AdminQuery {
+    // Most recently updated first
+    #[graphql(guard = "Admin")]
+    async fn admin_metadata_statuses(
+        &self,
+        ctx: &Context<'_>,
+        state: Option<MetadataState>,
+        page: Option<ConnectionParams>,
+    ) -> Result<PositionConnection<MetadataStatus>> {
+        position_page(page, |limit, offset| {
+            datasource::get_metadata_statuses(ctx.require(), state, limit, offset)
+        })
+        .await
+    }
+
+    #[graphql(guard = "Admin")]
+    async fn admin_metadata_status(
+        &self,
+        ctx: &Context<'_>,
+        isrc: Isrc,
+    ) -> Result<Option<MetadataStatus>> {
+        datasource::get_metadata_status(ctx.require(), isrc)
+            .await
+            .coerce_gql_err()
+    }
+
+    // Most recent first, filter by FLAGGED for matches awaiting review
+    #[graphql(guard = "Admin")]
+    async fn admin_service_matches(
+        &self,
+        ctx: &Context<'_>,
+        state: Option<MatchState>,
+        page: Option<ConnectionParams>,
+    ) -> Result<PositionConnection<ServiceMatch>> {
+        position_page(page, |limit, offset| {
+            datasource::get_service_matches(ctx.require(), state, limit, offset)
+        })
+        .await
+    }
+}
+
+#[derive(Default)]
+pub struct AdminMutation;
+
+#[Object]
+impl AdminMutation {
+    #[graphql(guard = "Admin")]
+    async fn admin_metadata_refetch(
+        &self,
+        ctx: &Context<'_>,
+        isrc: Isrc,
+    ) -> Result<MetadataStatus> {
+        // Being pending again, the ISRC is fetched even if it was fetched before
+        let status = datasource::reset_metadata_status(ctx.require(), isrc.clone()).await?;
+        ctx.require::<RedisStorage<FetchMetadata>>()
+            .clone()
+            .push(FetchMetadata {
+                isrc,
+                refresh: false,
+            })
+            .await?;
+        Ok(status)
+    }
+
+    // Corrects a bad match among several MusicBrainz recordings, unpins if none is given
+    #[graphql(guard = "Admin")]
+    async fn admin_metadata_pin_recording(
+        &self,
+        ctx: &Context<'_>,
+        isrc: Isrc,
+        recording_mbid: Option<Uuid>,
+    ) -> Result<MetadataStatus> {
+        let status = datasource::pin_recording(ctx.require(), isrc.clone(), recording_mbid).await?;
+        ctx.require::<RedisStorage<FetchMetadata>>()
+            .clone()
+            .push(FetchMetadata {
+                isrc,
+                refresh: false,
+            })
+            .await?;
+        Ok(status)
+    }
+
+    #[graphql(guard = "Admin")]
+    async fn admin_metadata_set(
+        &self,
+        ctx: &Context<'_>,
+        isrc: Isrc,
+        metadata: MetadataInput,
+    ) -> Result<MetadataStatus> {
+        let status = datasource::set_metadata(ctx.require(), isrc.clone(), metadata).await?;
+        ctx.require::<PubSubHandle<Event>>()
+            .publish(
+                topic_metadata_updated(isrc.as_str()),
+                Event::system(EventPayload::MetadataUpdated {
+                    isrc: isrc.clone().into(),
+                }),
+            )
+            .await;
+        // The job leaves manual metadata alone, apart from mirroring its cover art
+        ctx.require::<RedisStorage<FetchMetadata>>()
+            .clone()
+            .push(FetchMetadata {
+                isrc,
+                refresh: false,
+            })
+            .await?;
+        Ok(status)
+    }
+
+    #[graphql(guard = "Admin")]
+    async fn admin_service_match_resolve(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+        accept: bool,
+    ) -> Result<ServiceMatch> {
+        let service_match = datasource::resolve_service_match(ctx.require(), id, accept).await?;
+        ctx.require::<PubSubHandle<Event>>()
+            .publish(
+                topic_service_ids_updated(service_match.isrc.as_str()),
+                Event::system(EventPayload::ServiceIdsUpdated {
+                    isrc: service_match.isrc.clone(),
+                }),
+            )
+            .await;
+        Ok(service_match)
+    }
+}
@@ -0,0 +1,83 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::domain::common::typedef::Service;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum MetadataState {
+    Pending,
+    Fetched,
+    NotFound,
+    Failed,
+}
+
+#[derive(Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct MetadataStatus {
+    pub isrc: String,
+    pub state: MetadataState,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+    pub next_retry_at: Option<DateTime<Utc>>,
+    // Set by hand, hence skipped by fetch and refresh jobs
+    pub manual: bool,
+    // Why the recording behind the metadata was chosen among several
+    pub match_reason: Option<String>,
+    pub pinned_recording_mbid: Option<Uuid>,
+}
+
+#[derive(InputObject)]
+pub struct MetadataInput {
+    pub name: String,
+    pub artist: String,
+    pub cover_art_url: Option<String>,
+    pub release_title: Option<String>,
+    // YYYY, YYYY-MM or YYYY-MM-DD
+    pub release_date: Option<String>,
+    pub length_ms: Option<i32>,
+    #[graphql(default)]
+    pub genres: Vec<String>,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum MatchState {
+    Accepted,
+    Flagged,
+    Rejected,
+}
+
+// A service track found by title, artist and duration for an ISRC the service did not know
+#[derive(Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct ServiceMatch {
+    pub id: i32,
+    pub isrc: String,
+    pub service: Service,
+    pub service_id: String,
+    // The service's ISRC for the track, if it has one
+    pub track_isrc: Option<String>,
+    pub name: String,
+    pub artist: String,
+    pub length_ms: Option<i32>,
+    pub confidence: f32,
+    pub state: MatchState,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,120 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use sea_orm::sea_query::Query;
+use sea_orm::IdenStatic;
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DeriveColumn, EntityTrait, EnumIter, Order,
+    QueryFilter, QueryOrder, QuerySelect,
+};
+use uuid::Uuid;
+
+use db::util::OptLimitOffset;
+use entity::artists::{Entity as ArtistEntity, Model as ArtistModel};
+use entity::isrc_metadata_artist_credits::Entity as ArtistCreditEntity;
+use entity::motifs::Entity as MotifEntity;
+use entity::{isrc_metadata_artist_credits, motifs, profile_follows};
+
+use crate::db;
+use crate::domain::artist::typedef::Artist;
+use crate::domain::motif::typedef::Motif;
+use crate::domain::track::typedef::Track;
+use crate::rest::util::{ApiResult, DataError};
+
+impl From<ArtistModel> for Artist {
+    fn from(model: ArtistModel) -> Self {
+        Self {
+            mbid: model.mbid,
+            name: model.name,
+        }
+    }
+}
+
+pub async fn get_by_mbid(db: &DatabaseConnection, mbid: Uuid) -> ApiResult<Artist> {
+    let model = ArtistEntity::find_by_id(mbid).one(db).await?;
+    let artist = model.ok_or(DataError::NotFound("Artist not found".to_owned()))?;
+    Ok(artist.into())
+}
+
+// Tracks credited to the artist that were shared at least once
+pub async fn get_tracks_by_mbid(
+    db: &DatabaseConnection,
+    mbid: Uuid,
+    limit: Option<u64>,
+    offset: Option<u64>,
+) -> ApiResult<Vec<Track>> {
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+    enum QueryAs {
+        Isrc,
+    }
+    let isrcs: Vec<String> = ArtistCreditEntity::find()
+        .select_only()
+        .distinct()
+        .column_as(isrc_metadata_artist_credits::Column::Isrc, QueryAs::Isrc)
+        .filter(isrc_metadata_artist_credits::Column::ArtistMbid.eq(mbid))
+        .filter(
+            isrc_metadata_artist_credits::Column::Isrc.in_subquery(
+                Query::select()
+                    .column(motifs::Column::Isrc)
+                    .from(motifs::Entity)
+                    .to_owned(),
+            ),
+        )
+        .order_by(isrc_metadata_artist_credits::Column::Isrc, Order::Asc)
+        .opt_limit_offset(limit, offset)
+        .into_values::<_, QueryAs>()
+        .all(db)
+        .await?;
+    Ok(isrcs.into_iter().map(|isrc| Track { isrc }).collect())
+}
+
+// Motifs of tracks credited to the artist, optionally only those created by profiles
+// the given profile follows
+pub async fn get_motifs_by_mbid(
+    db: &DatabaseConnection,
+    mbid: Uuid,
+    following_of: Option<Uuid>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+) -> ApiResult<Vec<Motif>> {
+    let mut condition = Condition::all().add(
+        motifs::Column::Isrc.in_subquery(
+            Query::select()
+                .column(isrc_metadata_artist_credits::Column::Isrc)
+                .from(isrc_metadata_artist_credits::Entity)
+                .cond_where(isrc_metadata_artist_credits::Column::ArtistMbid.eq(mbid))
+                .to_owned(),
+        ),
+    );
+    if let Some(profile_id) = following_of {
+        condition = condition.add(
+            motifs::Column::CreatorId.in_subquery(
+                Query::select()
+                    .column(profile_follows::Column::FollowedId)
+                    .from(profile_follows::Entity)
+                    .cond_where(profile_follows::Column::FollowerId.eq(profile_id))
+                    .to_owned(),
+            ),
+        );
+    }
+    let models = MotifEntity::find()
+        .filter(condition)
+        .order_by(motifs::Column::CreatedAt, Order::Desc)
+        .opt_limit_offset(limit, offset)
+        .all(db)
+        .await?;
+    Ok(models.into_iter().map(|model| model.into()).collect())
+}
@@ -0,0 +1,93 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use async_graphql::*;
+use async_graphql::{ComplexObject, Context, Object};
+use uuid::Uuid;
+
+use crate::domain::artist::datasource;
+use crate::domain::artist::typedef::Artist;
+use crate::domain::motif::typedef::{ArtistCredit, Motif};
+use crate::domain::track::typedef::Track;
+use crate::gql::auth::Authenticated;
+use crate::gql::connection::{position_page, PositionConnection};
+use crate::gql::util::{AuthClaims, CoerceGraphqlError, ConnectionParams, ContextDependencies};
+
+#[ComplexObject]
+impl Artist {
+    async fn tracks(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<ConnectionParams>,
+    ) -> Result<PositionConnection<Track>> {
+        position_page(page, |limit, offset| {
+            datasource::get_tracks_by_mbid(ctx.require(), self.mbid, limit, offset)
+        })
+        .await
+    }
+
+    async fn motifs(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<ConnectionParams>,
+    ) -> Result<PositionConnection<Motif>> {
+        position_page(page, |limit, offset| {
+            datasource::get_motifs_by_mbid(ctx.require(), self.mbid, None, limit, offset)
+        })
+        .await
+    }
+
+    // Motifs of the artist by followed profiles
+    #[graphql(guard = "Authenticated")]
+    async fn following_motifs(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<ConnectionParams>,
+    ) -> Result<PositionConnection<Motif>> {
+        let own_id = ctx.require::<AuthClaims>().id;
+        position_page(page, |limit, offset| {
+            datasource::get_motifs_by_mbid(ctx.require(), self.mbid, Some(own_id), limit, offset)
+        })
+        .await
+    }
+}
+
+#[ComplexObject]
+impl ArtistCredit {
+    // Only known for credits from MusicBrainz
+    async fn artist(&self, ctx: &Context<'_>) -> Result<Option<Artist>> {
+        match self.artist_mbid {
+            Some(mbid) => datasource::get_by_mbid(ctx.require(), mbid)
+                .await
+                .map(Some)
+                .coerce_gql_err(),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ArtistQuery;
+
+#[Object]
+impl ArtistQuery {
+    #[graphql(guard = "Authenticated")]
+    async fn artist_by_mbid(&self, ctx: &Context<'_>, mbid: Uuid) -> Result<Artist> {
+        datasource::get_by_mbid(ctx.require(), mbid)
+            .await
+            .coerce_gql_err()
+    }
+}
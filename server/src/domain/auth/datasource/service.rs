@@ -142,6 +142,7 @@ pub async fn upsert_from_service_login(
                         email: Set(account.email.clone()),
                         created_at: Set(Utc::now().with_timezone(&FixedOffset::east(0))),
                         updated_at: Set(Utc::now().with_timezone(&FixedOffset::east(0))),
+                        is_admin: NotSet,
                     };
                     let user = user.insert(txn).await?;
 
@@ -19,7 +19,6 @@
 use async_graphql::*;
 use async_graphql::{ComplexObject, Context, Object};
 use async_graphql::dataloader::DataLoader;
-use fred::prelude::RedisValue;
 
 use crate::domain::comment::datasource;
 use crate::domain::comment::pubsub::{topic_comment_created, topic_comment_deleted};
@@ -31,6 +30,7 @@ use crate::domain::comment::dataloader::CommentLikedLoader;
 use crate::gql::auth::Authenticated;
 use crate::gql::connection::{position_page, PositionConnection};
 use crate::gql::util::{AuthClaims, CoerceGraphqlError, ConnectionParams, ContextDependencies};
+use crate::pubsub::event::{Event, EventPayload};
 use crate::PubSubHandle;
 
 #[ComplexObject]
@@ -118,8 +118,12 @@ impl CommentMutation {
         let comment =
             datasource::create(ctx.require(), own_id.clone(), Some(motif_id), None, args).await?;
         let topic = topic_comment_created(motif_id);
-        ctx.require::<PubSubHandle<RedisValue>>()
-            .publish(topic, RedisValue::Integer(comment.id.into()))
+        let payload = EventPayload::CommentCreated {
+            motif_id,
+            comment_id: comment.id,
+        };
+        ctx.require::<PubSubHandle<Event>>()
+            .publish(topic, Event::new(own_id, payload))
             .await;
         Ok(comment)
     }
@@ -150,8 +154,12 @@ impl CommentMutation {
         let deleted = datasource::delete_by_id(ctx.require(), own_id.clone(), comment_id).await?;
         if deleted {
             let topic = topic_comment_deleted(comment.motif_id);
-            ctx.require::<PubSubHandle<RedisValue>>()
-                .publish(topic, RedisValue::Integer(comment.id.into()))
+            let payload = EventPayload::CommentDeleted {
+                motif_id: comment.motif_id,
+                comment_id: comment.id,
+            };
+            ctx.require::<PubSubHandle<Event>>()
+                .publish(topic, Event::new(own_id, payload))
                 .await;
         }
         Ok(deleted)
@@ -14,12 +14,183 @@
  * limitations under the License.
  */
 
-use async_graphql::Enum;
-use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-#[derive(Enum, Copy, Clone, Eq, PartialEq, Serialize)]
+use async_graphql::{Enum, InputValueError, InputValueResult, Scalar, ScalarType, Value};
+use serde::{Deserialize, Serialize};
+
+#[derive(Enum, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Service {
     Spotify,
     #[graphql(name = "APPLE_MUSIC")]
     AppleMusic,
 }
+
+// International Standard Recording Code in its normalized 12 character form, e.g. USSM17800433:
+// country code (2 letters), registrant (3 alphanumerics), year (2 digits), designation (5 digits)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Isrc(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IsrcError {
+    Character(char),
+    Length(usize),
+    Country(String),
+    Year(String),
+    Designation(String),
+}
+
+impl Isrc {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Isrc {
+    type Err = IsrcError;
+
+    // Accepts any case and the hyphenated display form, e.g. us-sm1-78-00433
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .trim()
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        if let Some(c) = normalized.chars().find(|c| !c.is_ascii_alphanumeric()) {
+            return Err(IsrcError::Character(c));
+        }
+        if normalized.len() != 12 {
+            return Err(IsrcError::Length(normalized.len()));
+        }
+        let country = &normalized[0..2];
+        if !country.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(IsrcError::Country(country.to_owned()));
+        }
+        // The registrant code may contain letters and digits alike
+        let year = &normalized[5..7];
+        if !year.chars().all(|c| c.is_ascii_digit()) {
+            return Err(IsrcError::Year(year.to_owned()));
+        }
+        let designation = &normalized[7..12];
+        if !designation.chars().all(|c| c.is_ascii_digit()) {
+            return Err(IsrcError::Designation(designation.to_owned()));
+        }
+        Ok(Isrc(normalized))
+    }
+}
+
+impl TryFrom<String> for Isrc {
+    type Error = IsrcError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Isrc> for String {
+    fn from(isrc: Isrc) -> Self {
+        isrc.0
+    }
+}
+
+impl Display for Isrc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Display for IsrcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IsrcError::Character(c) => write!(f, "Invalid ISRC: Unexpected character '{}'", c),
+            IsrcError::Length(length) => write!(
+                f,
+                "Invalid ISRC: Expected 12 characters without hyphens, got {}",
+                length
+            ),
+            IsrcError::Country(country) => {
+                write!(
+                    f,
+                    "Invalid ISRC: Country code \"{}\" must be letters",
+                    country
+                )
+            }
+            IsrcError::Year(year) => write!(f, "Invalid ISRC: Year \"{}\" must be digits", year),
+            IsrcError::Designation(designation) => write!(
+                f,
+                "Invalid ISRC: Designation code \"{}\" must be digits",
+                designation
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IsrcError {}
+
+#[Scalar(name = "Isrc")]
+impl ScalarType for Isrc {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(isrc) => isrc.parse().map_err(InputValueError::custom),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_case_and_hyphens() {
+        assert_eq!(
+            "us-sm1-78-00433".parse::<Isrc>().unwrap().as_str(),
+            "USSM17800433"
+        );
+        assert_eq!(
+            " USSM17800433 ".parse::<Isrc>().unwrap().as_str(),
+            "USSM17800433"
+        );
+        // Registrant codes may contain digits
+        assert_eq!(
+            "GB1E10900123".parse::<Isrc>().unwrap().as_str(),
+            "GB1E10900123"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_isrcs() {
+        assert_eq!(
+            "US SM1 78 00433".parse::<Isrc>(),
+            Err(IsrcError::Character(' '))
+        );
+        assert_eq!("USSM178004".parse::<Isrc>(), Err(IsrcError::Length(10)));
+        assert_eq!("".parse::<Isrc>(), Err(IsrcError::Length(0)));
+        assert_eq!(
+            "U1SM17800433".parse::<Isrc>(),
+            Err(IsrcError::Country("U1".to_owned()))
+        );
+        assert_eq!(
+            "USSM1A700433".parse::<Isrc>(),
+            Err(IsrcError::Year("A7".to_owned()))
+        );
+        assert_eq!(
+            "USSM1780043X".parse::<Isrc>(),
+            Err(IsrcError::Designation("0043X".to_owned()))
+        );
+    }
+
+    #[test]
+    fn deserializes_normalized() {
+        let isrc: Isrc = serde_json::from_str("\"ussm17800433\"").unwrap();
+        assert_eq!(isrc.as_str(), "USSM17800433");
+        assert!(serde_json::from_str::<Isrc>("\"nope\"").is_err());
+    }
+}
@@ -24,7 +24,7 @@ use sea_orm::{
 use std::collections::HashMap;
 use uuid::Uuid;
 
-use entity::{motifs, profile_follows, profiles};
+use entity::{isrc_metadata_genres, motifs, profile_follows, profiles};
 use motifs::Entity as MotifEntity;
 use profile_follows::Entity as ProfileFollowEntity;
 use profiles::Entity as ProfileEntity;
@@ -32,6 +32,7 @@ use profiles::Entity as ProfileEntity;
 use crate::db::util::OptLimitOffset;
 use crate::domain::motif::typedef::Motif;
 use crate::domain::profile::typedef::Profile;
+use crate::metadata::provider::normalize_genre;
 use crate::rest::util::ApiResult;
 
 pub async fn get_motifs_by_profile_id(
@@ -40,10 +41,42 @@ pub async fn get_motifs_by_profile_id(
     after: Option<DateTime<Utc>>,
     before: Option<DateTime<Utc>>,
     limit: Option<u64>,
+) -> ApiResult<Vec<Motif>> {
+    get_following_motifs(db, profile_id, Condition::all(), after, before, limit).await
+}
+
+pub async fn get_motifs_by_profile_id_and_genre(
+    db: &DatabaseConnection,
+    profile_id: Uuid,
+    genre: String,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    limit: Option<u64>,
+) -> ApiResult<Vec<Motif>> {
+    let condition = Condition::all().add(
+        motifs::Column::Isrc.in_subquery(
+            Query::select()
+                .column(isrc_metadata_genres::Column::Isrc)
+                .from(isrc_metadata_genres::Entity)
+                .cond_where(isrc_metadata_genres::Column::Genre.eq(normalize_genre(&genre)))
+                .to_owned(),
+        ),
+    );
+    get_following_motifs(db, profile_id, condition, after, before, limit).await
+}
+
+// Motifs created by profiles the given profile follows, newest first
+async fn get_following_motifs(
+    db: &DatabaseConnection,
+    profile_id: Uuid,
+    condition: Condition,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    limit: Option<u64>,
 ) -> ApiResult<Vec<Motif>> {
     let mut query = MotifEntity::find()
         .filter(
-            Condition::all()
+            condition
                 .add(
                     motifs::Column::CreatorId.in_subquery(
                         Query::select()
@@ -52,6 +52,31 @@ impl FeedQuery {
         .await
     }
 
+    // Motifs of followed profiles whose track is tagged with the genre
+    #[graphql(guard = "Authenticated")]
+    async fn motifs_by_genre(
+        &self,
+        ctx: &Context<'_>,
+        genre: String,
+        page: Option<ConnectionParams>,
+    ) -> Result<FieldCursorConnection<DateTimeCursor, Motif>> {
+        field_cursor_page(
+            page,
+            |after, before, limit| {
+                datasource::get_motifs_by_profile_id_and_genre(
+                    ctx.require(),
+                    ctx.require::<AuthClaims>().id,
+                    genre,
+                    after.map(Into::into),
+                    before.map(Into::into),
+                    limit,
+                )
+            },
+            |node| node.created_at.clone().into(),
+        )
+        .await
+    }
+
     #[graphql(guard = "Authenticated")]
     async fn feed_profiles(
         &self,
@@ -16,19 +16,18 @@
 
 #![allow(dead_code)]
 
-use async_graphql::{Context, Object, Subscription};
-use fred::prelude::RedisValue;
+use async_graphql::{Context, Object, Result, Subscription};
 use futures::Stream;
 use futures_util::StreamExt;
 use sea_orm::DbErr;
-use uuid::Uuid;
 
 use crate::domain::like::datasource;
 use crate::domain::like::pubsub::topic_motif_liked;
 use crate::domain::profile;
 use crate::domain::profile::typedef::Profile;
 use crate::gql::auth::Authenticated;
-use crate::gql::util::{AuthClaims, ContextDependencies};
+use crate::gql::util::{AuthClaims, CoerceGraphqlError, ContextDependencies, EventNode};
+use crate::pubsub::event::{Event, EventPayload};
 use crate::PubSubHandle;
 
 #[derive(Default)]
@@ -42,8 +41,11 @@ impl LikeMutation {
         let new = datasource::like_motif(ctx.require(), own_id, motif_id).await?;
         if new {
             let topic = topic_motif_liked(motif_id);
-            ctx.require::<PubSubHandle<RedisValue>>()
-                .publish(topic, RedisValue::String(own_id.to_string().into()))
+            ctx.require::<PubSubHandle<Event>>()
+                .publish(
+                    topic,
+                    Event::new(own_id, EventPayload::MotifLiked { motif_id }),
+                )
                 .await;
         }
         Ok(new)
@@ -72,6 +74,32 @@ impl LikeMutation {
     }
 }
 
+async fn motif_liked_stream<'a>(
+    ctx: &'a Context<'_>,
+    motif_id: i32,
+    after_event_id: Option<String>,
+) -> Result<impl Stream<Item = EventNode<Profile>> + 'a> {
+    let subscription = ctx
+        .require::<PubSubHandle<Event>>()
+        .subscribe_after(vec![topic_motif_liked(motif_id)], after_event_id)
+        .await
+        .coerce_gql_err()?;
+    Ok(subscription.stream().filter_map(move |event| async move {
+        match event.payload {
+            EventPayload::MotifLiked { .. } => {
+                let profile = profile::datasource::get_by_id(ctx.require(), event.actor_id?)
+                    .await
+                    .ok()?;
+                Some(EventNode {
+                    event_id: event.id,
+                    node: profile,
+                })
+            }
+            _ => None,
+        }
+    }))
+}
+
 #[derive(Default)]
 pub struct LikeSubscription;
 
@@ -82,22 +110,19 @@ impl LikeSubscription {
         &'a self,
         ctx: &'a Context<'_>,
         motif_id: i32,
-    ) -> impl Stream<Item = Profile> + 'a {
-        let subscription = ctx
-            .require::<PubSubHandle<RedisValue>>()
-            .subscribe(vec![topic_motif_liked(motif_id)])
-            .await;
-        subscription.stream().filter_map(move |value| async move {
-            if let RedisValue::String(profile_id) = value {
-                profile::datasource::get_by_id(
-                    ctx.require(),
-                    Uuid::parse_str(&profile_id.to_string()).unwrap(),
-                )
-                .await
-                .ok()
-            } else {
-                None
-            }
-        })
+    ) -> Result<impl Stream<Item = Profile> + 'a> {
+        Ok(motif_liked_stream(ctx, motif_id, None)
+            .await?
+            .map(|event| event.node))
+    }
+
+    #[graphql(guard = "Authenticated")]
+    async fn motif_liked_events<'a>(
+        &'a self,
+        ctx: &'a Context<'_>,
+        motif_id: i32,
+        after_event_id: Option<String>,
+    ) -> Result<impl Stream<Item = EventNode<Profile>> + 'a> {
+        motif_liked_stream(ctx, motif_id, after_event_id).await
     }
 }
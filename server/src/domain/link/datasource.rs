@@ -0,0 +1,288 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use apalis::prelude::Storage;
+use apalis::redis::RedisStorage;
+use log::error;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+use entity::isrc_services;
+use entity::sea_orm_active_enums::Service as DbService;
+
+use crate::domain::common::typedef::{Isrc, Service};
+use crate::domain::link::typedef::{ResolvedLink, ServiceLink};
+use crate::domain::motif::datasource::get_metadata_by_isrc;
+use crate::domain::motif::typedef::ServiceId;
+use crate::metadata::provider::ServiceIdProviders;
+use crate::metadata::service_ids::BackfillServiceIds;
+use crate::metadata::FetchMetadata;
+use crate::rest::util::{ApiResult, DataError, GeneralError};
+
+// Resolves the linked track to its ISRC. IDs on the other services are looked up by the
+// backfill and metadata is fetched in the background, see motifServiceIdsUpdated and
+// motifMetadataUpdated
+pub async fn resolve_link(
+    db: &DatabaseConnection,
+    providers: &ServiceIdProviders,
+    metadata_job_storage: &RedisStorage<FetchMetadata>,
+    service_id_job_storage: &RedisStorage<BackfillServiceIds>,
+    link: ServiceLink,
+) -> ApiResult<ResolvedLink> {
+    let resolved = lookup_link(db, providers, &link).await?;
+
+    let missing = providers.services().into_iter().any(|service| {
+        let service: Service = service.into();
+        !resolved.service_ids.iter().any(|id| id.service == service)
+    });
+    if missing {
+        if let Err(err) = service_id_job_storage
+            .clone()
+            .push(BackfillServiceIds {
+                isrc: resolved.isrc.clone(),
+                linked: Some(link),
+            })
+            .await
+        {
+            error!("resolve_link: {}", err);
+        }
+    }
+
+    if resolved.metadata.is_none() {
+        if let Err(err) = metadata_job_storage
+            .clone()
+            .push(FetchMetadata {
+                isrc: resolved.isrc.clone(),
+                refresh: false,
+            })
+            .await
+        {
+            error!("resolve_link: {}", err);
+        }
+    }
+
+    Ok(resolved)
+}
+
+// What is known about the linked track without waiting for jobs
+async fn lookup_link(
+    db: &DatabaseConnection,
+    providers: &ServiceIdProviders,
+    link: &ServiceLink,
+) -> ApiResult<ResolvedLink> {
+    let isrc = resolve_isrc(db, providers, link).await?;
+
+    let mut service_ids: Vec<ServiceId> = isrc_services::Entity::find()
+        .filter(isrc_services::Column::Isrc.eq(isrc.to_string()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|model| model.into())
+        .collect();
+    // Stored by the backfill, along with the IDs on the other services
+    if !service_ids.iter().any(|id| id.service == link.service) {
+        service_ids.push(ServiceId {
+            service: link.service,
+            id: link.id.clone(),
+        });
+    }
+    let metadata = get_metadata_by_isrc(db, isrc.to_string()).await?;
+
+    Ok(ResolvedLink {
+        isrc,
+        metadata,
+        service_ids,
+    })
+}
+
+// Tracks resolved before are not looked up in the catalog again
+async fn resolve_isrc(
+    db: &DatabaseConnection,
+    providers: &ServiceIdProviders,
+    link: &ServiceLink,
+) -> ApiResult<Isrc> {
+    let linked_service: DbService = link.service.into();
+    let known = isrc_services::Entity::find()
+        .filter(isrc_services::Column::Service.eq(linked_service.clone()))
+        .filter(isrc_services::Column::ServiceId.eq(link.id.clone()))
+        .one(db)
+        .await?;
+    let isrc = match known {
+        Some(model) => model.isrc,
+        None => {
+            let provider = providers
+                .get(&linked_service)
+                .ok_or(DataError::Invalid(format!(
+                    "{:?} links are not supported",
+                    linked_service
+                )))?;
+            provider
+                .lookup_isrc(&link.id)
+                .await
+                .map_err(|err| {
+                    error!(
+                        "ISRC lookup for {:?} ID {} failed: {}",
+                        linked_service, &link.id, err
+                    );
+                    GeneralError::Internal
+                })?
+                .ok_or(DataError::NotFound("Track not found".to_owned()))?
+        }
+    };
+    let isrc = isrc
+        .parse()
+        .map_err(|err| DataError::NotFound(format!("Track has no valid ISRC: {}", err)))?;
+    Ok(isrc)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ActiveModelTrait, NotSet, Set, SqlxPostgresConnector};
+    use sqlx::PgPool;
+
+    use crate::metadata::provider::spotify::{SpotifyCredentials, SpotifyProvider};
+    use crate::metadata::ratelimit::RateLimiter;
+    use crate::metadata::stub;
+    use crate::rest::util::ApiError;
+
+    use super::*;
+
+    fn spotify_providers() -> ServiceIdProviders {
+        let urls = stub::catalog_urls(stub::spawn_fixture_stub());
+        // Any credentials will do for the stub
+        let credentials = SpotifyCredentials {
+            client_id: "stub".to_owned(),
+            client_secret: "stub".to_owned(),
+        };
+        ServiceIdProviders::new(vec![Box::new(SpotifyProvider::new(
+            RateLimiter::new(None),
+            credentials,
+            urls.spotify_token,
+            urls.spotify_api,
+        ))])
+    }
+
+    fn link(link: &str) -> ServiceLink {
+        link.parse().unwrap()
+    }
+
+    #[sqlx::test]
+    async fn resolves_link_through_catalog(pool: PgPool) {
+        let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+        let providers = spotify_providers();
+        for linked in [
+            "spotify:track:0000000000ZZMTF2200001",
+            "https://open.spotify.com/track/0000000000ZZMTF2200001",
+        ] {
+            let isrc = resolve_isrc(&db, &providers, &link(linked)).await.unwrap();
+            assert_eq!(isrc.as_str(), "ZZMTF2200001");
+        }
+    }
+
+    fn service_ids(resolved: &ResolvedLink) -> Vec<(Service, &str)> {
+        resolved
+            .service_ids
+            .iter()
+            .map(|service_id| (service_id.service, service_id.id.as_str()))
+            .collect()
+    }
+
+    #[sqlx::test]
+    async fn new_track_only_has_linked_id(pool: PgPool) {
+        let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+        let linked = link("spotify:track:0000000000ZZMTF2200001");
+        let resolved = lookup_link(&db, &spotify_providers(), &linked)
+            .await
+            .unwrap();
+        assert_eq!(resolved.isrc.as_str(), "ZZMTF2200001");
+        // Both follow once the jobs pushed by resolve_link are done
+        assert!(resolved.metadata.is_none());
+        assert_eq!(
+            service_ids(&resolved),
+            vec![(Service::Spotify, "0000000000ZZMTF2200001")]
+        );
+    }
+
+    #[sqlx::test]
+    async fn known_track_has_stored_ids(pool: PgPool) {
+        let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+        for (service, service_id) in [
+            (DbService::Spotify, "0000000000ZZMTF2200005"),
+            (DbService::AppleMusic, "1000000005"),
+        ] {
+            isrc_services::ActiveModel {
+                id: NotSet,
+                isrc: Set("ZZMTF2200005".to_owned()),
+                service: Set(service),
+                service_id: Set(service_id.to_owned()),
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
+
+        let linked = link("spotify:track:0000000000ZZMTF2200005");
+        let resolved = lookup_link(&db, &spotify_providers(), &linked)
+            .await
+            .unwrap();
+        let mut ids = service_ids(&resolved);
+        ids.sort_by_key(|(_, id)| *id);
+        assert_eq!(
+            ids,
+            vec![
+                (Service::Spotify, "0000000000ZZMTF2200005"),
+                (Service::AppleMusic, "1000000005"),
+            ]
+        );
+    }
+
+    #[sqlx::test]
+    async fn resolves_known_link_without_catalog(pool: PgPool) {
+        let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+        isrc_services::ActiveModel {
+            id: NotSet,
+            isrc: Set("ZZMTF2200005".to_owned()),
+            service: Set(DbService::Spotify),
+            service_id: Set("0000000000ZZMTF2200005".to_owned()),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let providers = ServiceIdProviders::new(vec![]);
+        let linked = link("spotify:track:0000000000ZZMTF2200005");
+        let isrc = resolve_isrc(&db, &providers, &linked).await.unwrap();
+        assert_eq!(isrc.as_str(), "ZZMTF2200005");
+    }
+
+    #[sqlx::test]
+    async fn unknown_track_is_not_found(pool: PgPool) {
+        let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+        let linked = link("spotify:track:0000000000000000000000");
+        let result = resolve_isrc(&db, &spotify_providers(), &linked).await;
+        assert!(matches!(
+            result,
+            Err(ApiError::Data(DataError::NotFound(_)))
+        ));
+    }
+
+    #[sqlx::test]
+    async fn unsupported_service_is_invalid(pool: PgPool) {
+        let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+        let linked = link("https://music.apple.com/us/song/stub-song/1000000002");
+        let result = resolve_isrc(&db, &spotify_providers(), &linked).await;
+        assert!(matches!(result, Err(ApiError::Data(DataError::Invalid(_)))));
+    }
+}
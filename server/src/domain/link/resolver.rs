@@ -0,0 +1,50 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use async_graphql::*;
+use async_graphql::{Context, Object};
+
+use crate::domain::link::datasource;
+use crate::domain::link::typedef::{ResolvedLink, ServiceLink};
+use crate::gql::auth::Authenticated;
+use crate::gql::util::{CoerceGraphqlError, ContextDependencies};
+use crate::rest::util::DataError;
+
+#[derive(Default)]
+pub struct LinkQuery;
+
+#[Object]
+impl LinkQuery {
+    /// Takes a Spotify or Apple Music track link or URI, as shared from the apps. Returns what
+    /// is known right away, metadata and the IDs on other services of tracks seen for the
+    /// first time follow through motifMetadataUpdated and motifServiceIdsUpdated
+    #[graphql(guard = "Authenticated")]
+    async fn link_resolve(&self, ctx: &Context<'_>, link: String) -> Result<ResolvedLink> {
+        let link = link
+            .parse::<ServiceLink>()
+            .map_err(DataError::Invalid)
+            .coerce_gql_err()?;
+        datasource::resolve_link(
+            ctx.require(),
+            ctx.require(),
+            ctx.require(),
+            ctx.require(),
+            link,
+        )
+        .await
+        .coerce_gql_err()
+    }
+}
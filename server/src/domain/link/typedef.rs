@@ -0,0 +1,198 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str::FromStr;
+
+use async_graphql::SimpleObject;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::common::typedef::{Isrc, Service};
+use crate::domain::motif::typedef::{Metadata, ServiceId};
+
+const SPOTIFY_ID_LENGTH: usize = 22;
+
+// A track as shared from a streaming app, identified by the service's own ID
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceLink {
+    pub service: Service,
+    pub id: String,
+}
+
+// Shaped like CreateMotif, so that it can be passed on as is
+#[derive(SimpleObject, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedLink {
+    pub isrc: Isrc,
+    /// Null for tracks seen for the first time. The metadata is fetched in the background,
+    /// subscribe to motifMetadataUpdated to receive it
+    pub metadata: Option<Metadata>,
+    /// The linked ID and the IDs known so far. For tracks seen for the first time this is only
+    /// the linked ID, the others are looked up in the background, subscribe to
+    /// motifServiceIdsUpdated to receive them
+    pub service_ids: Vec<ServiceId>,
+}
+
+impl FromStr for ServiceLink {
+    type Err = String;
+
+    // Accepts spotify:track:<id>, open.spotify.com/track/<id> and music.apple.com song links,
+    // either /<storefront>/song/<slug>/<id> or /<storefront>/album/<slug>/<album id>?i=<id>
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let link = s.trim();
+        if let Some(id) = link.strip_prefix("spotify:track:") {
+            return spotify_link(id);
+        }
+        let url = Url::parse(link).map_err(|_| format!("Not a link: {}", link))?;
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+            .unwrap_or_default();
+        match url.host_str() {
+            Some("open.spotify.com") | Some("play.spotify.com") => {
+                // Localized links are prefixed, e.g. /intl-de/track/<id>
+                segments
+                    .iter()
+                    .position(|segment| *segment == "track")
+                    .and_then(|index| segments.get(index + 1))
+                    .ok_or(format!("Not a Spotify track link: {}", link))
+                    .and_then(|id| spotify_link(id))
+            }
+            Some("music.apple.com") | Some("geo.music.apple.com") | Some("itunes.apple.com") => {
+                // Songs opened from an album are selected by the i parameter
+                if let Some((_, id)) = url.query_pairs().find(|(key, _)| key == "i") {
+                    return apple_music_link(&id);
+                }
+                match segments.get(1) {
+                    Some(&"song") => segments
+                        .last()
+                        .ok_or(format!("Not an Apple Music song link: {}", link))
+                        .and_then(|id| apple_music_link(id)),
+                    _ => Err(format!("Not an Apple Music song link: {}", link)),
+                }
+            }
+            _ => Err(format!("Unsupported link: {}", link)),
+        }
+    }
+}
+
+// Base62, always 22 characters long
+fn spotify_link(id: &str) -> Result<ServiceLink, String> {
+    if id.len() != SPOTIFY_ID_LENGTH || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid Spotify track ID: {}", id));
+    }
+    Ok(ServiceLink {
+        service: Service::Spotify,
+        id: id.to_owned(),
+    })
+}
+
+fn apple_music_link(id: &str) -> Result<ServiceLink, String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("Invalid Apple Music song ID: {}", id));
+    }
+    Ok(ServiceLink {
+        service: Service::AppleMusic,
+        id: id.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(link: &str) -> Result<ServiceLink, String> {
+        link.parse()
+    }
+
+    fn spotify(id: &str) -> Result<ServiceLink, String> {
+        Ok(ServiceLink {
+            service: Service::Spotify,
+            id: id.to_owned(),
+        })
+    }
+
+    fn apple_music(id: &str) -> Result<ServiceLink, String> {
+        Ok(ServiceLink {
+            service: Service::AppleMusic,
+            id: id.to_owned(),
+        })
+    }
+
+    #[test]
+    fn spotify_uri() {
+        assert_eq!(
+            parse("spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+            spotify("4uLU6hMCjMI75M1A2tKUQC")
+        );
+        assert_eq!(
+            parse("  spotify:track:4uLU6hMCjMI75M1A2tKUQC\n"),
+            spotify("4uLU6hMCjMI75M1A2tKUQC")
+        );
+    }
+
+    #[test]
+    fn spotify_url() {
+        assert_eq!(
+            parse("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC"),
+            spotify("4uLU6hMCjMI75M1A2tKUQC")
+        );
+        assert_eq!(
+            parse("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=0123456789abcdef"),
+            spotify("4uLU6hMCjMI75M1A2tKUQC")
+        );
+        assert_eq!(
+            parse("https://open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC"),
+            spotify("4uLU6hMCjMI75M1A2tKUQC")
+        );
+        assert_eq!(
+            parse("https://play.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC/"),
+            spotify("4uLU6hMCjMI75M1A2tKUQC")
+        );
+    }
+
+    #[test]
+    fn apple_music_url() {
+        assert_eq!(
+            parse("https://music.apple.com/us/album/stub-album/1000000000?i=1000000001"),
+            apple_music("1000000001")
+        );
+        assert_eq!(
+            parse("https://music.apple.com/us/song/stub-song/1000000002"),
+            apple_music("1000000002")
+        );
+        assert_eq!(
+            parse("https://geo.music.apple.com/de/album/stub-album/1000000000?l=en&i=1000000001"),
+            apple_music("1000000001")
+        );
+    }
+
+    #[test]
+    fn malformed_links() {
+        assert!(parse("").is_err());
+        assert!(parse("not a link").is_err());
+        assert!(parse("spotify:track:").is_err());
+        assert!(parse("spotify:track:4uLU6hMCjMI75M1A2tKUQ").is_err());
+        assert!(parse("spotify:track:4uLU6hMCjMI75M1A2tKUQ!").is_err());
+        assert!(parse("spotify:album:4uLU6hMCjMI75M1A2tKUQC").is_err());
+        assert!(parse("https://open.spotify.com/album/4uLU6hMCjMI75M1A2tKUQC").is_err());
+        assert!(parse("https://open.spotify.com/track/").is_err());
+        assert!(parse("https://music.apple.com/us/album/stub-album/1000000000").is_err());
+        assert!(parse("https://music.apple.com/us/album/stub-album/1000000000?i=").is_err());
+        assert!(parse("https://music.apple.com/us/song/stub-song/abc").is_err());
+        assert!(parse("https://example.com/track/4uLU6hMCjMI75M1A2tKUQC").is_err());
+    }
+}
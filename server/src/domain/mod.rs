@@ -14,11 +14,15 @@
  * limitations under the License.
  */
 
+pub mod admin;
+pub mod artist;
 pub mod auth;
 pub mod collection;
 pub mod comment;
 pub mod common;
 pub mod feed;
 pub mod like;
+pub mod link;
 pub mod motif;
 pub mod profile;
+pub mod track;
@@ -29,16 +29,26 @@ use uuid::Uuid;
 
 use db::util::OptLimitOffset;
 use entity::isrc_metadata::{Entity as MetadataEntity, Model as MetadataModel};
+use entity::isrc_metadata_artist_credits::{
+    Entity as ArtistCreditEntity, Model as ArtistCreditModel,
+};
+use entity::isrc_metadata_genres::{Entity as GenreEntity, Model as GenreModel};
 use entity::isrc_services::{Entity as IsrcServiceEntity, Model as IsrcServiceModel};
 use entity::motif_listeners::Entity as MotifListenerEntity;
 use entity::motifs::{Entity as MotifEntity, Model as MotifModel};
 use entity::profiles::{Entity as ProfileEntity, Model as ProfileModel};
-use entity::{isrc_metadata, isrc_services, motif_listeners, motifs};
+use entity::{
+    isrc_metadata, isrc_metadata_artist_credits, isrc_metadata_genres, isrc_services,
+    motif_listeners, motifs,
+};
 
 use crate::db;
 use crate::domain::common::typedef::Service;
-use crate::domain::motif::typedef::{CreateMotif, Metadata, Motif, ServiceId};
+use crate::domain::motif::typedef::{
+    ArtistCredit, CoverArt, CreateMotif, Metadata, Motif, ServiceId,
+};
 use crate::domain::profile::typedef::Profile;
+use crate::metadata::artwork::{cover_art_url, CoverArtSize};
 use crate::rest::util::{ApiError, ApiResult, DataError};
 
 impl From<MotifModel> for Motif {
@@ -62,12 +72,36 @@ impl From<IsrcServiceModel> for ServiceId {
     }
 }
 
-impl From<MetadataModel> for Metadata {
-    fn from(model: MetadataModel) -> Self {
+impl From<ArtistCreditModel> for ArtistCredit {
+    fn from(model: ArtistCreditModel) -> Self {
+        Self {
+            name: model.name,
+            join_phrase: model.join_phrase,
+            artist_mbid: model.artist_mbid,
+        }
+    }
+}
+
+impl From<(MetadataModel, Vec<ArtistCreditModel>, Vec<GenreModel>)> for Metadata {
+    fn from(
+        (model, credits, genres): (MetadataModel, Vec<ArtistCreditModel>, Vec<GenreModel>),
+    ) -> Self {
         Self {
             name: model.name,
             artist: model.artist,
+            artists: credits.into_iter().map(Into::into).collect(),
+            cover_art: model.cover_art_mirrored_at.map(|_| CoverArt {
+                small_url: cover_art_url(&model.isrc, CoverArtSize::Small),
+                medium_url: cover_art_url(&model.isrc, CoverArtSize::Medium),
+                large_url: cover_art_url(&model.isrc, CoverArtSize::Large),
+            }),
             cover_art_url: model.cover_art_url,
+            release_title: model.release_title,
+            release_date: model.release_date,
+            length_ms: model.length_ms,
+            recording_mbid: model.recording_mbid,
+            release_mbid: model.release_mbid,
+            genres: genres.into_iter().map(|genre| genre.genre).collect(),
         }
     }
 }
@@ -205,13 +239,46 @@ pub async fn get_metadata_all(
         .filter(isrc_metadata::Column::Isrc.is_in(isrcs.to_owned()))
         .all(db)
         .await?;
+    let credit_models: Vec<ArtistCreditModel> = ArtistCreditEntity::find()
+        .filter(isrc_metadata_artist_credits::Column::Isrc.is_in(isrcs.to_owned()))
+        .order_by_asc(isrc_metadata_artist_credits::Column::Position)
+        .all(db)
+        .await?;
+    let mut credits: HashMap<String, Vec<ArtistCreditModel>> = HashMap::new();
+    for credit in credit_models {
+        credits.entry(credit.isrc.clone()).or_default().push(credit);
+    }
+    let genre_models: Vec<GenreModel> = GenreEntity::find()
+        .filter(isrc_metadata_genres::Column::Isrc.is_in(isrcs.to_owned()))
+        .order_by_asc(isrc_metadata_genres::Column::Position)
+        .all(db)
+        .await?;
+    let mut genres: HashMap<String, Vec<GenreModel>> = HashMap::new();
+    for genre in genre_models {
+        genres.entry(genre.isrc.clone()).or_default().push(genre);
+    }
     let mapped: HashMap<String, Metadata> = models
         .into_iter()
-        .map(|model| (model.isrc.clone(), model.into()))
+        .map(|model| {
+            let model_credits = credits.remove(&model.isrc).unwrap_or_default();
+            let model_genres = genres.remove(&model.isrc).unwrap_or_default();
+            (
+                model.isrc.clone(),
+                (model, model_credits, model_genres).into(),
+            )
+        })
         .collect();
     Ok(mapped)
 }
 
+pub async fn get_metadata_by_isrc(
+    db: &DatabaseConnection,
+    isrc: String,
+) -> Result<Option<Metadata>, ApiError> {
+    let mut metadata = get_metadata_all(db, &[isrc.clone()]).await?;
+    Ok(metadata.remove(&isrc))
+}
+
 pub async fn listen_by_id(
     db: &DatabaseConnection,
     listener_id: Uuid,
@@ -272,7 +339,7 @@ pub async fn create(
         Box::pin(async move {
             let model = motifs::ActiveModel {
                 id: NotSet,
-                isrc: Set(input.isrc),
+                isrc: Set(input.isrc.into()),
                 offset: Set(input.offset),
                 created_at: Set(Utc::now().with_timezone(&FixedOffset::east(0))),
                 creator_id: Set(creator_id),
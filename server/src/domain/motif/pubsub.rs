@@ -27,3 +27,11 @@ pub fn topic_motif_deleted(author_id: Uuid) -> String {
 pub fn topic_motif_listened(motif_id: i32) -> String {
     format!("MOTIF_LISTENED.{}", motif_id)
 }
+
+pub fn topic_metadata_updated(isrc: &str) -> String {
+    format!("METADATA_UPDATED.{}", isrc)
+}
+
+pub fn topic_service_ids_updated(isrc: &str) -> String {
+    format!("SERVICE_IDS_UPDATED.{}", isrc)
+}
@@ -22,26 +22,33 @@ use async_graphql::dataloader::DataLoader;
 use async_graphql::futures_util::Stream;
 use async_graphql::*;
 use async_graphql::{ComplexObject, Context, Object, Subscription};
-use fred::prelude::RedisValue;
+use async_stream::stream;
 use futures_util::StreamExt;
 use log::error;
 use uuid::Uuid;
 
 use crate::domain::comment::typedef::Comment;
+use crate::domain::common::typedef::Isrc;
 use crate::domain::motif::dataloader::{
     MotifLikedLoader, MotifListenedLoader, MotifMetadataLoader,
 };
 use crate::domain::motif::datasource;
 use crate::domain::motif::pubsub::{
-    topic_motif_created, topic_motif_deleted, topic_motif_listened,
+    topic_metadata_updated, topic_motif_created, topic_motif_deleted, topic_motif_listened,
+    topic_service_ids_updated,
 };
 use crate::domain::motif::typedef::{CreateMotif, Metadata, Motif, ServiceId};
+use crate::domain::profile::pubsub::topic_profile_following;
 use crate::domain::profile::typedef::Profile;
+use crate::domain::track::typedef::Track;
 use crate::domain::{comment, like, profile};
 use crate::gql::auth::Authenticated;
 use crate::gql::connection::{position_page, PositionConnection};
-use crate::gql::util::{AuthClaims, CoerceGraphqlError, ConnectionParams, ContextDependencies};
+use crate::gql::util::{
+    AuthClaims, CoerceGraphqlError, ConnectionParams, ContextDependencies, EventNode,
+};
 use crate::metadata::FetchMetadata;
+use crate::pubsub::event::{Event, EventPayload};
 use crate::PubSubHandle;
 
 #[ComplexObject]
@@ -55,6 +62,12 @@ impl Motif {
             .coerce_gql_err()
     }
 
+    async fn track(&self) -> Track {
+        Track {
+            isrc: self.isrc.clone(),
+        }
+    }
+
     async fn service_ids(&self, ctx: &Context<'_>) -> Result<Vec<ServiceId>> {
         datasource::get_service_ids_by_isrc(ctx.require(), self.isrc.clone())
             .await
@@ -158,13 +171,15 @@ impl MotifMutation {
     #[graphql(guard = "Authenticated")]
     async fn motif_create(&self, ctx: &Context<'_>, args: CreateMotif) -> Result<Motif> {
         let own_id = ctx.require::<AuthClaims>().id;
+        let isrc = args.isrc.clone();
         let motif = datasource::create(ctx.require(), own_id.clone(), args).await?;
 
         if let Err(err) = ctx
             .require::<RedisStorage<FetchMetadata>>()
             .clone()
             .push(FetchMetadata {
-                isrc: motif.isrc.clone(),
+                isrc,
+                refresh: false,
             })
             .await
         {
@@ -172,8 +187,19 @@ impl MotifMutation {
         }
 
         let topic = topic_motif_created(own_id);
-        ctx.require::<PubSubHandle<RedisValue>>()
-            .publish(topic, RedisValue::Integer(motif.id.into()))
+        let event = Event {
+            timestamp: motif.created_at,
+            ..Event::new(
+                own_id,
+                EventPayload::MotifCreated {
+                    motif_id: motif.id,
+                    isrc: motif.isrc.clone(),
+                    offset: motif.offset,
+                },
+            )
+        };
+        ctx.require::<PubSubHandle<Event>>()
+            .publish(topic, event)
             .await;
 
         Ok(motif)
@@ -185,8 +211,11 @@ impl MotifMutation {
         let deleted = datasource::delete_by_id(ctx.require(), own_id.clone(), motif_id).await?;
         if deleted {
             let topic = topic_motif_deleted(own_id);
-            ctx.require::<PubSubHandle<RedisValue>>()
-                .publish(topic, RedisValue::Integer(motif_id.into()))
+            ctx.require::<PubSubHandle<Event>>()
+                .publish(
+                    topic,
+                    Event::new(own_id, EventPayload::MotifDeleted { motif_id }),
+                )
                 .await;
         }
         Ok(deleted)
@@ -198,14 +227,96 @@ impl MotifMutation {
         let is_new = datasource::listen_by_id(ctx.require(), own_id.clone(), motif_id).await?;
         if is_new {
             let topic = topic_motif_listened(motif_id);
-            ctx.require::<PubSubHandle<RedisValue>>()
-                .publish(topic, RedisValue::String(own_id.to_string().into()))
+            ctx.require::<PubSubHandle<Event>>()
+                .publish(
+                    topic,
+                    Event::new(own_id, EventPayload::MotifListened { motif_id }),
+                )
                 .await;
         }
         Ok(is_new)
     }
 }
 
+// Events on the per-author topics of all followed profiles. The topic set
+// is kept in sync with follows and unfollows made during the subscription
+async fn following_stream<'a>(
+    ctx: &'a Context<'_>,
+    topic: fn(Uuid) -> String,
+    after_event_id: Option<String>,
+) -> impl Stream<Item = Event> + 'a {
+    let own_id = ctx.require::<AuthClaims>().id;
+    stream! {
+        // Without the follows or the subscription there is nothing to receive, so
+        // errors end the stream
+        let following_ids =
+            match profile::datasource::get_following_ids(ctx.require(), own_id).await {
+                Ok(following_ids) => following_ids,
+                Err(err) => {
+                    error!("following_stream: {}", err);
+                    return;
+                }
+            };
+        let mut topics: Vec<String> = following_ids.into_iter().map(topic).collect();
+        topics.push(topic_profile_following(own_id));
+        let mut subscription = match ctx
+            .require::<PubSubHandle<Event>>()
+            .subscribe_after(topics, after_event_id)
+            .await
+        {
+            Ok(subscription) => subscription,
+            Err(err) => {
+                error!("following_stream: {}", err);
+                return;
+            }
+        };
+        while let Some(event) = subscription.receive().await {
+            match event.payload {
+                EventPayload::ProfileFollowed { profile_id } => {
+                    // Keeps the stream alive for the follows that are subscribed already
+                    if let Err(err) = subscription.add_topics(vec![topic(profile_id)]).await {
+                        error!("following_stream: {}", err);
+                    }
+                }
+                EventPayload::ProfileUnfollowed { profile_id } => {
+                    if let Err(err) = subscription.remove_topics(vec![topic(profile_id)]).await {
+                        error!("following_stream: {}", err);
+                        return;
+                    }
+                }
+                _ => yield event,
+            }
+        }
+    }
+}
+
+async fn motif_created_stream<'a>(
+    ctx: &'a Context<'_>,
+    after_event_id: Option<String>,
+) -> impl Stream<Item = EventNode<Motif>> + 'a {
+    following_stream(ctx, topic_motif_created, after_event_id)
+        .await
+        .filter_map(|event| async move {
+            match event.payload {
+                EventPayload::MotifCreated {
+                    motif_id,
+                    isrc,
+                    offset,
+                } => Some(EventNode {
+                    event_id: event.id,
+                    node: Motif {
+                        id: motif_id,
+                        isrc,
+                        offset,
+                        created_at: event.timestamp,
+                        creator_id: event.actor_id?,
+                    },
+                }),
+                _ => None,
+            }
+        })
+}
+
 #[derive(Default)]
 pub struct MotifSubscription;
 
@@ -213,50 +324,30 @@ pub struct MotifSubscription;
 impl MotifSubscription {
     #[graphql(guard = "Authenticated")]
     async fn motif_created<'a>(&'a self, ctx: &'a Context<'_>) -> impl Stream<Item = Motif> + 'a {
-        let following_ids =
-            profile::datasource::get_following_ids(ctx.require(), ctx.require::<AuthClaims>().id)
-                .await
-                .unwrap();
-        let topics: Vec<String> = following_ids
-            .into_iter()
-            .map(|id| topic_motif_created(id))
-            .collect();
-        let subscription = ctx
-            .require::<PubSubHandle<RedisValue>>()
-            .subscribe(topics)
-            .await;
-        subscription.stream().filter_map(move |value| async move {
-            if let RedisValue::Integer(motif_id) = value {
-                datasource::get_by_id(ctx.require(), motif_id as i32)
-                    .await
-                    .ok()
-            } else {
-                None
-            }
-        })
+        motif_created_stream(ctx, None)
+            .await
+            .map(|event| event.node)
+    }
+
+    #[graphql(guard = "Authenticated")]
+    async fn motif_created_events<'a>(
+        &'a self,
+        ctx: &'a Context<'_>,
+        after_event_id: Option<String>,
+    ) -> impl Stream<Item = EventNode<Motif>> + 'a {
+        motif_created_stream(ctx, after_event_id).await
     }
 
     #[graphql(guard = "Authenticated")]
     async fn motif_deleted<'a>(&'a self, ctx: &'a Context<'_>) -> impl Stream<Item = i32> + 'a {
-        let following_ids =
-            profile::datasource::get_following_ids(ctx.require(), ctx.require::<AuthClaims>().id)
-                .await
-                .unwrap();
-        let topics: Vec<String> = following_ids
-            .into_iter()
-            .map(|id| topic_motif_deleted(id))
-            .collect();
-        let subscription = ctx
-            .require::<PubSubHandle<RedisValue>>()
-            .subscribe(topics)
-            .await;
-        subscription.stream().filter_map(move |value| async move {
-            if let RedisValue::Integer(motif_id) = value {
-                Some(motif_id as i32)
-            } else {
-                None
-            }
-        })
+        following_stream(ctx, topic_motif_deleted, None)
+            .await
+            .filter_map(|event| async move {
+                match event.payload {
+                    EventPayload::MotifDeleted { motif_id } => Some(motif_id),
+                    _ => None,
+                }
+            })
     }
 
     #[graphql(guard = "Authenticated")]
@@ -264,21 +355,82 @@ impl MotifSubscription {
         &'a self,
         ctx: &'a Context<'_>,
         motif_id: i32,
-    ) -> impl Stream<Item = Profile> + 'a {
+    ) -> Result<impl Stream<Item = Profile> + 'a> {
         let subscription = ctx
-            .require::<PubSubHandle<RedisValue>>()
+            .require::<PubSubHandle<Event>>()
             .subscribe(vec![topic_motif_listened(motif_id)])
-            .await;
-        subscription.stream().filter_map(move |value| async move {
-            if let RedisValue::String(listener_id) = value {
-                profile::datasource::get_by_id(
-                    ctx.require(),
-                    Uuid::parse_str(&listener_id.to_string()).unwrap(),
-                )
-                .await
-                .ok()
-            } else {
-                None
+            .await
+            .coerce_gql_err()?;
+        Ok(subscription.stream().filter_map(move |event| async move {
+            match event.payload {
+                EventPayload::MotifListened { .. } => {
+                    profile::datasource::get_by_id(ctx.require(), event.actor_id?)
+                        .await
+                        .ok()
+                }
+                _ => None,
+            }
+        }))
+    }
+
+    // Starts with the current metadata if it already arrived before subscribing
+    #[graphql(guard = "Authenticated")]
+    async fn motif_metadata_updated<'a>(
+        &'a self,
+        ctx: &'a Context<'_>,
+        isrc: Isrc,
+    ) -> Result<impl Stream<Item = Metadata> + 'a> {
+        let mut subscription = ctx
+            .require::<PubSubHandle<Event>>()
+            .subscribe(vec![topic_metadata_updated(isrc.as_str())])
+            .await
+            .coerce_gql_err()?;
+        let isrc: String = isrc.into();
+        Ok(stream! {
+            if let Ok(Some(metadata)) =
+                datasource::get_metadata_by_isrc(ctx.require(), isrc.clone()).await
+            {
+                yield metadata;
+            }
+            while let Some(event) = subscription.receive().await {
+                if let EventPayload::MetadataUpdated { .. } = event.payload {
+                    if let Ok(Some(metadata)) =
+                        datasource::get_metadata_by_isrc(ctx.require(), isrc.clone()).await
+                    {
+                        yield metadata;
+                    }
+                }
+            }
+        })
+    }
+
+    // Starts with the IDs stored before subscribing, e.g. by the backfill after linkResolve
+    #[graphql(guard = "Authenticated")]
+    async fn motif_service_ids_updated<'a>(
+        &'a self,
+        ctx: &'a Context<'_>,
+        isrc: Isrc,
+    ) -> Result<impl Stream<Item = Vec<ServiceId>> + 'a> {
+        let mut subscription = ctx
+            .require::<PubSubHandle<Event>>()
+            .subscribe(vec![topic_service_ids_updated(isrc.as_str())])
+            .await
+            .coerce_gql_err()?;
+        let isrc: String = isrc.into();
+        Ok(stream! {
+            if let Ok(service_ids) =
+                datasource::get_service_ids_by_isrc(ctx.require(), isrc.clone()).await
+            {
+                yield service_ids;
+            }
+            while let Some(event) = subscription.receive().await {
+                if let EventPayload::ServiceIdsUpdated { .. } = event.payload {
+                    if let Ok(service_ids) =
+                        datasource::get_service_ids_by_isrc(ctx.require(), isrc.clone()).await
+                    {
+                        yield service_ids;
+                    }
+                }
             }
         })
     }
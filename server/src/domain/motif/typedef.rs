@@ -16,9 +16,10 @@
 
 use async_graphql::{InputObject, SimpleObject};
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 use uuid::Uuid;
 
-use crate::domain::common::typedef::Service;
+use crate::domain::common::typedef::{Isrc, Service};
 
 #[derive(Clone, SimpleObject)]
 #[graphql(complex)]
@@ -32,22 +33,50 @@ pub struct Motif {
 
 #[derive(InputObject)]
 pub struct CreateMotif {
-    pub isrc: String,
+    pub isrc: Isrc,
     pub service_ids: Vec<ServiceIdInput>,
     pub offset: i32,
 }
 
-#[derive(SimpleObject)]
+#[derive(SimpleObject, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ServiceId {
     pub service: Service,
     pub id: String,
 }
 
-#[derive(Clone, SimpleObject)]
+#[derive(Clone, SimpleObject, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Metadata {
     pub name: String,
     pub artist: String,
+    pub artists: Vec<ArtistCredit>,
     pub cover_art_url: Option<String>,
+    // Mirrored thumbnails, once available
+    pub cover_art: Option<CoverArt>,
+    pub release_title: Option<String>,
+    pub release_date: Option<String>,
+    pub length_ms: Option<i32>,
+    pub recording_mbid: Option<Uuid>,
+    pub release_mbid: Option<Uuid>,
+    pub genres: Vec<String>,
+}
+
+#[derive(Clone, SimpleObject, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverArt {
+    pub small_url: String,
+    pub medium_url: String,
+    pub large_url: String,
+}
+
+#[derive(Clone, SimpleObject, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[graphql(complex)]
+pub struct ArtistCredit {
+    pub name: String,
+    pub join_phrase: String,
+    pub artist_mbid: Option<Uuid>,
 }
 
 #[derive(InputObject)]
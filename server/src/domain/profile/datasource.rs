@@ -231,8 +231,8 @@ pub async fn unfollow(
         follower_id: Set(follower_id),
         followed_id: Set(followed_id),
     };
-    model.delete(db).await?;
-    Ok(true)
+    let result = model.delete(db).await?;
+    Ok(result.rows_affected > 0)
 }
 
 pub async fn is_username_available(db: &DatabaseConnection, username: String) -> ApiResult<bool> {
@@ -23,3 +23,8 @@ pub fn topic_profile_updated(profile_id: Uuid) -> String {
 pub fn topic_profile_followed(profile_id: Uuid) -> String {
     format!("PROFILE_FOLLOWED.{}", profile_id.as_hyphenated())
 }
+
+// Follows and unfollows by the given profile, as opposed to follows of it
+pub fn topic_profile_following(profile_id: Uuid) -> String {
+    format!("PROFILE_FOLLOWING.{}", profile_id.as_hyphenated())
+}
@@ -20,7 +20,6 @@ use async_graphql::connection::{ConnectionNameType, EdgeNameType};
 use async_graphql::dataloader::DataLoader;
 use async_graphql::futures_util::Stream;
 use async_graphql::*;
-use fred::prelude::RedisValue;
 use futures::stream::StreamExt;
 use uuid::Uuid;
 
@@ -29,14 +28,19 @@ use crate::domain::motif::dataloader::MotifsByProfileLoader;
 use crate::domain::motif::typedef::Motif;
 use crate::domain::profile::dataloader::ProfileFollowsLoader;
 use crate::domain::profile::datasource;
-use crate::domain::profile::pubsub::{topic_profile_followed, topic_profile_updated};
+use crate::domain::profile::pubsub::{
+    topic_profile_followed, topic_profile_following, topic_profile_updated,
+};
 use crate::domain::profile::typedef::{Profile, ProfileUpdate};
 use crate::domain::{collection, motif};
 use crate::gql::auth::Authenticated;
 use crate::gql::connection::{
     field_cursor_page, position_page, DateTimeCursor, FieldCursorConnection, PositionConnection,
 };
-use crate::gql::util::{AuthClaims, CoerceGraphqlError, ConnectionParams, ContextDependencies};
+use crate::gql::util::{
+    AuthClaims, CoerceGraphqlError, ConnectionParams, ContextDependencies, EventNode,
+};
+use crate::pubsub::event::{Event, EventPayload};
 use crate::PubSubHandle;
 
 struct MotifsByCreatedAtConnection;
@@ -227,8 +231,11 @@ impl ProfileMutation {
         let profile_id = ctx.require::<AuthClaims>().id;
         let updated = datasource::update_by_id(ctx.require(), profile_id.clone(), update).await?;
         let topic = topic_profile_updated(profile_id);
-        ctx.require::<PubSubHandle<RedisValue>>()
-            .publish(topic, RedisValue::String("".into()))
+        ctx.require::<PubSubHandle<Event>>()
+            .publish(
+                topic,
+                Event::new(profile_id, EventPayload::ProfileUpdated { profile_id }),
+            )
             .await;
         Ok(updated)
     }
@@ -240,64 +247,102 @@ impl ProfileMutation {
             .await
             .coerce_gql_err()?;
         if new {
-            let topic = topic_profile_followed(profile_id);
-            ctx.require::<PubSubHandle<RedisValue>>()
-                .publish(topic, RedisValue::String(own_id.to_string().into()))
+            let pubsub = ctx.require::<PubSubHandle<Event>>();
+            let event = Event::new(own_id, EventPayload::ProfileFollowed { profile_id });
+            pubsub
+                .publish(topic_profile_followed(profile_id), event.clone())
                 .await;
+            pubsub.publish(topic_profile_following(own_id), event).await;
         }
         Ok(new)
     }
 
     #[graphql(guard = "Authenticated")]
     async fn profile_unfollow_by_id(&self, ctx: &Context<'_>, profile_id: Uuid) -> Result<bool> {
-        datasource::unfollow(ctx.require(), ctx.require::<AuthClaims>().id, profile_id)
+        let own_id = ctx.require::<AuthClaims>().id;
+        let removed = datasource::unfollow(ctx.require(), own_id.clone(), profile_id)
             .await
-            .coerce_gql_err()
+            .coerce_gql_err()?;
+        if removed {
+            let topic = topic_profile_following(own_id);
+            ctx.require::<PubSubHandle<Event>>()
+                .publish(
+                    topic,
+                    Event::new(own_id, EventPayload::ProfileUnfollowed { profile_id }),
+                )
+                .await;
+        }
+        Ok(removed)
     }
 }
 
+async fn new_follower_stream<'a>(
+    ctx: &'a Context<'_>,
+    after_event_id: Option<String>,
+) -> Result<impl Stream<Item = EventNode<Profile>> + 'a> {
+    let profile_id: Uuid = ctx.require::<AuthClaims>().id;
+    let topic = topic_profile_followed(profile_id.clone());
+    let subscription = ctx
+        .require::<PubSubHandle<Event>>()
+        .subscribe_after(vec![topic], after_event_id)
+        .await
+        .coerce_gql_err()?;
+    Ok(subscription.stream().filter_map(move |event| async move {
+        match event.payload {
+            EventPayload::ProfileFollowed { .. } => {
+                let follower = datasource::get_by_id(ctx.require(), event.actor_id?)
+                    .await
+                    .ok()?;
+                Some(EventNode {
+                    event_id: event.id,
+                    node: follower,
+                })
+            }
+            _ => None,
+        }
+    }))
+}
+
 #[derive(Default)]
 pub struct ProfileSubscription;
 
 #[Subscription]
 impl ProfileSubscription {
     #[graphql(guard = "Authenticated")]
-    async fn profile_me<'a>(&self, ctx: &'a Context<'_>) -> impl Stream<Item = Profile> + 'a {
+    async fn profile_me<'a>(
+        &self,
+        ctx: &'a Context<'_>,
+    ) -> Result<impl Stream<Item = Profile> + 'a> {
         let profile_id: Uuid = ctx.require::<AuthClaims>().id;
         let topic = topic_profile_updated(profile_id.clone());
         let subscription = ctx
-            .require::<PubSubHandle<RedisValue>>()
+            .require::<PubSubHandle<Event>>()
             .subscribe(vec![topic])
-            .await;
-        subscription.stream().filter_map(move |_| async move {
+            .await
+            .coerce_gql_err()?;
+        Ok(subscription.stream().filter_map(move |_| async move {
             datasource::get_by_id(ctx.require(), profile_id.clone())
                 .await
                 .ok()
-        })
+        }))
     }
 
     #[graphql(guard = "Authenticated")]
     async fn profile_me_new_follower<'a>(
         &self,
         ctx: &'a Context<'_>,
-    ) -> impl Stream<Item = Profile> + 'a {
-        let profile_id: Uuid = ctx.require::<AuthClaims>().id;
-        let topic = topic_profile_followed(profile_id.clone());
-        let subscription = ctx
-            .require::<PubSubHandle<RedisValue>>()
-            .subscribe(vec![topic])
-            .await;
-        subscription.stream().filter_map(move |value| async move {
-            if let RedisValue::String(follower_id) = value {
-                datasource::get_by_id(
-                    ctx.require(),
-                    Uuid::parse_str(&follower_id.to_string()).unwrap(),
-                )
-                .await
-                .ok()
-            } else {
-                None
-            }
-        })
+    ) -> Result<impl Stream<Item = Profile> + 'a> {
+        Ok(new_follower_stream(ctx, None)
+            .await?
+            .map(|event| event.node))
+    }
+
+    #[graphql(guard = "Authenticated")]
+    async fn profile_me_new_follower_events<'a>(
+        &self,
+        ctx: &'a Context<'_>,
+        after_event_id: Option<String>,
+    ) -> Result<impl Stream<Item = EventNode<Profile>> + 'a> {
+        new_follower_stream(ctx, after_event_id).await
     }
 }
@@ -0,0 +1,93 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use sea_orm::sea_query::Query;
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, Order, PaginatorTrait, QueryFilter,
+    QueryOrder,
+};
+use uuid::Uuid;
+
+use db::util::OptLimitOffset;
+use entity::motifs::Entity as MotifEntity;
+use entity::profiles::Entity as ProfileEntity;
+use entity::{motifs, profile_follows, profiles};
+
+use crate::db;
+use crate::domain::motif::typedef::Motif;
+use crate::domain::profile::typedef::Profile;
+use crate::rest::util::ApiResult;
+
+pub async fn get_motifs_by_isrc(
+    db: &DatabaseConnection,
+    isrc: String,
+    limit: Option<u64>,
+    offset: Option<u64>,
+) -> ApiResult<Vec<Motif>> {
+    let models = MotifEntity::find()
+        .filter(motifs::Column::Isrc.eq(isrc))
+        .order_by(motifs::Column::CreatedAt, Order::Desc)
+        .opt_limit_offset(limit, offset)
+        .all(db)
+        .await?;
+    Ok(models.into_iter().map(|model| model.into()).collect())
+}
+
+pub async fn get_motifs_count_by_isrc(db: &DatabaseConnection, isrc: String) -> ApiResult<i32> {
+    MotifEntity::find()
+        .filter(motifs::Column::Isrc.eq(isrc))
+        .count(db)
+        .await
+        .map_err(|err| err.into())
+        .map(|count| count as i32)
+}
+
+// Followed profiles that created a motif with the ISRC
+pub async fn get_following_creators_by_isrc(
+    db: &DatabaseConnection,
+    profile_id: Uuid,
+    isrc: String,
+    limit: Option<u64>,
+    offset: Option<u64>,
+) -> ApiResult<Vec<Profile>> {
+    let models = ProfileEntity::find()
+        .filter(
+            Condition::all()
+                .add(
+                    profiles::Column::UserId.in_subquery(
+                        Query::select()
+                            .column(motifs::Column::CreatorId)
+                            .from(motifs::Entity)
+                            .cond_where(motifs::Column::Isrc.eq(isrc))
+                            .to_owned(),
+                    ),
+                )
+                .add(
+                    profiles::Column::UserId.in_subquery(
+                        Query::select()
+                            .column(profile_follows::Column::FollowedId)
+                            .from(profile_follows::Entity)
+                            .cond_where(profile_follows::Column::FollowerId.eq(profile_id))
+                            .to_owned(),
+                    ),
+                ),
+        )
+        .order_by(profiles::Column::Username, Order::Asc)
+        .opt_limit_offset(limit, offset)
+        .all(db)
+        .await?;
+    Ok(models.into_iter().map(|model| model.into()).collect())
+}
@@ -0,0 +1,92 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use async_graphql::dataloader::DataLoader;
+use async_graphql::*;
+use async_graphql::{ComplexObject, Context, Object};
+
+use crate::domain::common::typedef::Isrc;
+use crate::domain::motif;
+use crate::domain::motif::dataloader::MotifMetadataLoader;
+use crate::domain::motif::typedef::{Metadata, Motif, ServiceId};
+use crate::domain::profile::typedef::Profile;
+use crate::domain::track::datasource;
+use crate::domain::track::typedef::Track;
+use crate::gql::auth::Authenticated;
+use crate::gql::connection::{position_page, PositionConnection};
+use crate::gql::util::{AuthClaims, CoerceGraphqlError, ConnectionParams, ContextDependencies};
+
+#[ComplexObject]
+impl Track {
+    async fn metadata(&self, ctx: &Context<'_>) -> Result<Option<Metadata>> {
+        let loader: &DataLoader<MotifMetadataLoader> = ctx.require();
+        loader.load_one(self.isrc.clone()).await.coerce_gql_err()
+    }
+
+    async fn service_ids(&self, ctx: &Context<'_>) -> Result<Vec<ServiceId>> {
+        motif::datasource::get_service_ids_by_isrc(ctx.require(), self.isrc.clone())
+            .await
+            .coerce_gql_err()
+    }
+
+    // Number of times the track was shared
+    async fn motifs_count(&self, ctx: &Context<'_>) -> Result<i32> {
+        datasource::get_motifs_count_by_isrc(ctx.require(), self.isrc.clone())
+            .await
+            .coerce_gql_err()
+    }
+
+    async fn motifs(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<ConnectionParams>,
+    ) -> Result<PositionConnection<Motif>> {
+        position_page(page, |limit, offset| {
+            datasource::get_motifs_by_isrc(ctx.require(), self.isrc.clone(), limit, offset)
+        })
+        .await
+    }
+
+    #[graphql(guard = "Authenticated")]
+    async fn following_creators(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<ConnectionParams>,
+    ) -> Result<PositionConnection<Profile>> {
+        let own_id = ctx.require::<AuthClaims>().id;
+        position_page(page, |limit, offset| {
+            datasource::get_following_creators_by_isrc(
+                ctx.require(),
+                own_id,
+                self.isrc.clone(),
+                limit,
+                offset,
+            )
+        })
+        .await
+    }
+}
+
+#[derive(Default)]
+pub struct TrackQuery;
+
+#[Object]
+impl TrackQuery {
+    #[graphql(guard = "Authenticated")]
+    async fn track_by_isrc(&self, isrc: Isrc) -> Track {
+        Track { isrc: isrc.into() }
+    }
+}
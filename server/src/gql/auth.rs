@@ -14,9 +14,11 @@
  * limitations under the License.
  */
 
-use crate::gql::util::{AuthClaims, CoerceGraphqlError};
+use crate::gql::util::{AuthClaims, CoerceGraphqlError, ContextDependencies};
 use crate::rest::util::{ApiError, AuthenticationError};
 use async_graphql::*;
+use entity::users;
+use sea_orm::{DatabaseConnection, EntityTrait};
 
 pub(crate) struct Authenticated;
 
@@ -29,3 +31,25 @@ impl Guard for Authenticated {
             .map(|_| ())
     }
 }
+
+pub(crate) struct Admin;
+
+#[async_trait::async_trait]
+impl Guard for Admin {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let claims = ctx
+            .data_opt::<AuthClaims>()
+            .ok_or(ApiError::Authentication(AuthenticationError::TokenInvalid))
+            .coerce_gql_err()?;
+        // Looked up on every request, so that revoking takes effect before tokens expire
+        let user = users::Entity::find_by_id(claims.id)
+            .one(ctx.require::<DatabaseConnection>())
+            .await
+            .map_err(ApiError::from)
+            .coerce_gql_err()?;
+        match user {
+            Some(user) if user.is_admin => Ok(()),
+            _ => Err(ApiError::Authorization("Admin role required".to_owned())).coerce_gql_err(),
+        }
+    }
+}
@@ -22,13 +22,15 @@ use async_graphql::{Schema, SchemaBuilder};
 use axum::http::Request;
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
-use fred::prelude::RedisValue;
 use sea_orm::DatabaseConnection;
 use crate::domain::comment::dataloader::CommentLikedLoader;
 
 use crate::gql::schema::{Mutation, Query, Subscription};
 use crate::gql::util::AuthClaims;
+use crate::metadata::provider::ServiceIdProviders;
+use crate::metadata::service_ids::BackfillServiceIds;
 use crate::metadata::FetchMetadata;
+use crate::pubsub::event::Event;
 use crate::PubSubHandle;
 
 pub async fn schema_middleware_auth<B>(
@@ -40,9 +42,9 @@ pub async fn schema_middleware_auth<B>(
         .get::<DatabaseConnection>()
         .unwrap()
         .clone();
-    let pubsub: PubSubHandle<RedisValue> = req
+    let pubsub: PubSubHandle<Event> = req
         .extensions()
-        .get::<PubSubHandle<RedisValue>>()
+        .get::<PubSubHandle<Event>>()
         .unwrap()
         .clone();
     let storage: RedisStorage<FetchMetadata> = req
@@ -50,6 +52,16 @@ pub async fn schema_middleware_auth<B>(
         .get::<RedisStorage<FetchMetadata>>()
         .unwrap()
         .clone();
+    let service_id_providers: ServiceIdProviders = req
+        .extensions()
+        .get::<ServiceIdProviders>()
+        .unwrap()
+        .clone();
+    let service_id_job_storage: RedisStorage<BackfillServiceIds> = req
+        .extensions()
+        .get::<RedisStorage<BackfillServiceIds>>()
+        .unwrap()
+        .clone();
     let claims: Option<AuthClaims> = req
         .extensions()
         .get::<Option<AuthClaims>>()
@@ -62,7 +74,9 @@ pub async fn schema_middleware_auth<B>(
     )
     .data(db.clone())
     .data(pubsub)
-    .data(storage);
+    .data(storage)
+    .data(service_id_providers)
+    .data(service_id_job_storage);
     if let Some(claims) = claims {
         builder = builder.data(claims.clone());
         builder = add_data_loaders(builder, db, claims);
@@ -84,9 +98,9 @@ pub async fn schema_middleware<B>(
         .get::<DatabaseConnection>()
         .unwrap()
         .clone();
-    let pubsub: PubSubHandle<RedisValue> = req
+    let pubsub: PubSubHandle<Event> = req
         .extensions()
-        .get::<PubSubHandle<RedisValue>>()
+        .get::<PubSubHandle<Event>>()
         .unwrap()
         .clone();
     let schema = Schema::build(
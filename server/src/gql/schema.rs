@@ -16,24 +16,33 @@
 
 use async_graphql::{MergedObject, MergedSubscription, Schema};
 
+use crate::domain::admin::resolver::{AdminMutation, AdminQuery};
+use crate::domain::artist::resolver::ArtistQuery;
 use crate::domain::collection::resolver::{CollectionMutation, CollectionQuery};
 use crate::domain::comment::resolver::{CommentMutation, CommentQuery};
 use crate::domain::feed::resolver::FeedQuery;
 use crate::domain::like::resolver::{LikeMutation, LikeSubscription};
+use crate::domain::link::resolver::LinkQuery;
 use crate::domain::motif::resolver::{MotifMutation, MotifQuery, MotifSubscription};
 use crate::domain::profile::resolver::{ProfileMutation, ProfileQuery, ProfileSubscription};
+use crate::domain::track::resolver::TrackQuery;
 
 #[derive(MergedObject, Default)]
 pub struct Query(
     FeedQuery,
+    AdminQuery,
+    ArtistQuery,
     CollectionQuery,
     CommentQuery,
+    LinkQuery,
     MotifQuery,
     ProfileQuery,
+    TrackQuery,
 );
 
 #[derive(MergedObject, Default)]
 pub struct Mutation(
+    AdminMutation,
     CollectionMutation,
     CommentMutation,
     LikeMutation,
@@ -17,9 +17,12 @@
 use std::fmt::Display;
 use std::sync::Arc;
 
-use async_graphql::{Context, ErrorExtensions, InputObject, Result};
+use async_graphql::{Context, ErrorExtensions, InputObject, OutputType, Result, SimpleObject};
 use uuid::Uuid;
 
+use crate::domain::motif::typedef::Motif;
+use crate::domain::profile::typedef::Profile;
+
 pub trait ContextDependencies {
     fn require<T: Sync + Send + 'static>(&self) -> &T;
     fn require_arc<T: Sync + Send + 'static>(&self) -> &T;
@@ -56,3 +59,18 @@ pub struct ConnectionParams {
     pub first: Option<i32>,
     pub last: Option<i32>,
 }
+
+// Subscription item carrying the id of the underlying event,
+// which clients can pass back to resume after reconnecting
+#[derive(SimpleObject)]
+#[graphql(
+    concrete(name = "MotifEvent", params(Motif)),
+    concrete(name = "ProfileEvent", params(Profile))
+)]
+pub struct EventNode<T: OutputType> {
+    /// Id to pass as afterEventId when resubscribing. Only engines that retain history
+    /// (PUBSUB_ENGINE=redis_streams) assign ids, on all others it is null and afterEventId
+    /// is ignored
+    pub event_id: Option<String>,
+    pub node: T,
+}
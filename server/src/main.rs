@@ -23,7 +23,9 @@ use apalis::redis::RedisStorage;
 use std::env;
 use std::error::Error;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use async_graphql::futures_util::{pin_mut, StreamExt};
@@ -31,9 +33,10 @@ use axum::{Extension, Router, Server};
 use axum_server::tls_rustls::RustlsConfig;
 use dotenvy::dotenv;
 use env_logger::Target;
-use fred::types::RedisValue;
+use fred::clients::RedisClient;
+use fred::prelude::{ClientLike, ReconnectPolicy, RedisConfig};
 use futures_util::future;
-use log::{info, LevelFilter};
+use log::{info, warn, LevelFilter};
 use sea_orm::{DatabaseConnection, SqlxPostgresConnector};
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use sqlx::ConnectOptions;
@@ -41,10 +44,23 @@ use tokio::sync::Mutex;
 use tower::ServiceBuilder;
 
 use crate::gql::routing::graphql_router;
-use crate::metadata::{fetch_metadata, schedule_fetch_metadata, FetchMetadata};
-use crate::pubsub::prelude::{PubSub, PubSubHandle};
+use crate::metadata::artwork::CoverArtMirror;
+use crate::metadata::provider::{CatalogUrls, MetadataProviders, ProviderKind, ServiceIdProviders};
+use crate::metadata::ratelimit::RateLimiter;
+use crate::metadata::service_ids::{
+    backfill_service_ids, schedule_backfill_service_ids, BackfillServiceIds,
+};
+use crate::metadata::{
+    fetch_metadata, schedule_fetch_metadata, schedule_refresh_metadata, FetchMetadata,
+};
+use crate::pubsub::event::Event;
+use crate::pubsub::memory::MemoryPubSubEngine;
+use crate::pubsub::postgres::PostgresPubSubEngine;
+use crate::pubsub::prelude::{OverflowPolicy, PubSub, PubSubConfig, PubSubHandle};
 use crate::pubsub::redis::RedisPubSubEngine;
+use crate::pubsub::redis_streams::RedisStreamPubSubEngine;
 use crate::rest::rest_router;
+use crate::storage::{Storage, StorageKind};
 
 mod db;
 mod domain;
@@ -52,6 +68,7 @@ mod gql;
 mod metadata;
 mod pubsub;
 mod rest;
+mod storage;
 
 async fn make_db_connection() -> DatabaseConnection {
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
@@ -77,35 +94,217 @@ async fn make_redis_connection() -> Box<Mutex<RedisPubSubEngine>> {
     RedisPubSubEngine::new(redis_url).await
 }
 
+async fn make_redis_stream_connection() -> Box<Mutex<RedisStreamPubSubEngine>> {
+    let redis_url = env::var("REDIS_URL").expect("REDIS_URL must be set");
+    let max_len = env::var("PUBSUB_STREAM_MAX_LEN")
+        .map(|len| {
+            len.parse::<i64>()
+                .expect("PUBSUB_STREAM_MAX_LEN must be a number")
+        })
+        .unwrap_or(1000);
+    RedisStreamPubSubEngine::new(redis_url, max_len).await
+}
+
+fn make_pubsub_config() -> PubSubConfig {
+    let default = PubSubConfig::default();
+    PubSubConfig {
+        buffer_size: env::var("PUBSUB_BUFFER_SIZE")
+            .map(|size| match size.parse::<usize>() {
+                Ok(size) if size > 0 => size,
+                _ => panic!("PUBSUB_BUFFER_SIZE must be a positive number, got \"{}\"", size),
+            })
+            .unwrap_or(default.buffer_size),
+        overflow_policy: env::var("PUBSUB_OVERFLOW_POLICY")
+            .map(|policy| {
+                policy.parse::<OverflowPolicy>().unwrap_or_else(|_| {
+                    panic!(
+                        "PUBSUB_OVERFLOW_POLICY must be drop_oldest, drop_newest or disconnect, got \"{}\"",
+                        policy
+                    )
+                })
+            })
+            .unwrap_or(default.overflow_policy),
+    }
+}
+
+async fn make_pubsub(db: &DatabaseConnection) -> PubSub<Event> {
+    let engine = env::var("PUBSUB_ENGINE").unwrap_or("redis".to_owned());
+    let config = make_pubsub_config();
+    match engine.as_str() {
+        "redis" => PubSub::connect(make_redis_connection().await, config).await,
+        "redis_streams" => PubSub::connect(make_redis_stream_connection().await, config).await,
+        "postgres" => {
+            let pool = db.get_postgres_connection_pool().clone();
+            let url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+            PubSub::connect(PostgresPubSubEngine::new(pool, url), config).await
+        }
+        "memory" => PubSub::connect(MemoryPubSubEngine::new(), config).await,
+        other => panic!("Unknown PUBSUB_ENGINE: {}", other),
+    }
+}
+
 async fn make_metadata_job_storage() -> RedisStorage<FetchMetadata> {
     let redis_url = env::var("REDIS_URL").expect("REDIS_URL must be set");
     RedisStorage::connect(redis_url).await.unwrap()
 }
 
+async fn make_service_id_job_storage() -> RedisStorage<BackfillServiceIds> {
+    let redis_url = env::var("REDIS_URL").expect("REDIS_URL must be set");
+    RedisStorage::connect(redis_url).await.unwrap()
+}
+
+async fn make_rate_limiter() -> RateLimiter {
+    let redis_url = env::var("REDIS_URL").expect("REDIS_URL must be set");
+    let client = RedisClient::new(RedisConfig::from_url(&redis_url).unwrap());
+    client.connect(Some(ReconnectPolicy::default()));
+    match client.wait_for_connect().await {
+        Ok(_) => RateLimiter::new(Some(Arc::new(client))),
+        Err(err) => {
+            warn!("Rate limiter: Falling back to in-memory buckets: {}", err);
+            RateLimiter::new(None)
+        }
+    }
+}
+
+// The catalog stub stands in for all catalogs if it is built in and configured
+fn make_catalog_urls() -> CatalogUrls {
+    #[cfg(feature = "catalog-stub")]
+    {
+        if let Some(addr) = spawn_catalog_stub() {
+            return metadata::stub::catalog_urls(addr);
+        }
+    }
+    let defaults = CatalogUrls::default();
+    CatalogUrls {
+        musicbrainz: env::var("MUSICBRAINZ_BASE_URL").unwrap_or(defaults.musicbrainz),
+        cover_art_archive: env::var("COVER_ART_ARCHIVE_BASE_URL")
+            .unwrap_or(defaults.cover_art_archive),
+        spotify_token: env::var("SPOTIFY_TOKEN_URL").unwrap_or(defaults.spotify_token),
+        spotify_api: env::var("SPOTIFY_API_BASE_URL").unwrap_or(defaults.spotify_api),
+        apple_music_api: env::var("APPLE_MUSIC_API_BASE_URL").unwrap_or(defaults.apple_music_api),
+    }
+}
+
+fn make_metadata_providers(
+    rate_limiter: &RateLimiter,
+    catalog_urls: &CatalogUrls,
+) -> MetadataProviders {
+    let providers = env::var("METADATA_PROVIDERS").unwrap_or("musicbrainz".to_owned());
+    MetadataProviders::new(
+        providers
+            .split(',')
+            .map(|name| {
+                ProviderKind::from_str(name.trim())
+                    .unwrap()
+                    .create(rate_limiter.clone(), catalog_urls)
+            })
+            .collect(),
+    )
+}
+
+fn make_service_id_providers(
+    rate_limiter: &RateLimiter,
+    catalog_urls: &CatalogUrls,
+) -> ServiceIdProviders {
+    let providers = env::var("SERVICE_ID_PROVIDERS").unwrap_or("spotify,apple_music".to_owned());
+    ServiceIdProviders::new(
+        providers
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .filter_map(|name| {
+                ProviderKind::from_str(name.trim())
+                    .unwrap()
+                    .create_service_id_provider(rate_limiter.clone(), catalog_urls)
+            })
+            .collect(),
+    )
+}
+
+fn make_storage() -> Storage {
+    let backend = env::var("STORAGE_BACKEND").unwrap_or("local".to_owned());
+    match StorageKind::from_str(&backend).unwrap() {
+        StorageKind::Local => {
+            let root = env::var("STORAGE_LOCAL_ROOT").unwrap_or("./storage".to_owned());
+            Storage::local(PathBuf::from(root))
+        }
+    }
+}
+
+// Only started if fixtures are configured, see metadata::stub
+#[cfg(feature = "catalog-stub")]
+fn spawn_catalog_stub() -> Option<SocketAddr> {
+    let root = env::var("CATALOG_STUB_ROOT").ok()?;
+    let addr = env::var("CATALOG_STUB_ADDR")
+        .unwrap_or("127.0.0.1:8081".to_owned())
+        .parse::<SocketAddr>()
+        .expect("CATALOG_STUB_ADDR must be a socket address");
+    Some(metadata::stub::spawn_catalog_stub(PathBuf::from(root), addr).unwrap())
+}
+
 async fn set_up_app(
     db: &DatabaseConnection,
-    redis_pubsub: &PubSub<RedisValue>,
+    pubsub: &PubSub<Event>,
     metadata_job_storage: &RedisStorage<FetchMetadata>,
+    service_id_job_storage: &RedisStorage<BackfillServiceIds>,
+    service_id_providers: &ServiceIdProviders,
+    storage: &Storage,
 ) -> Router {
     Router::new()
         .nest("/", rest_router())
         .nest("/graphql", graphql_router())
         .layer(Extension(db.clone()))
-        .layer(Extension(PubSubHandle::from(redis_pubsub).await))
+        .layer(Extension(PubSubHandle::from(pubsub).await))
         .layer(Extension(metadata_job_storage.clone()))
+        .layer(Extension(service_id_job_storage.clone()))
+        .layer(Extension(service_id_providers.clone()))
+        .layer(Extension(storage.clone()))
 }
 
-async fn set_up_job_monitor(
-    db: &DatabaseConnection,
-    metadata_job_storage: &RedisStorage<FetchMetadata>,
-) -> Result<(), Box<dyn Error>> {
+// Shared by the job workers
+struct JobDependencies<'a> {
+    db: &'a DatabaseConnection,
+    pubsub: &'a PubSub<Event>,
+    metadata_job_storage: &'a RedisStorage<FetchMetadata>,
+    metadata_providers: &'a MetadataProviders,
+    service_id_job_storage: &'a RedisStorage<BackfillServiceIds>,
+    service_id_providers: &'a ServiceIdProviders,
+    storage: &'a Storage,
+    rate_limiter: &'a RateLimiter,
+}
+
+async fn set_up_job_monitor(dependencies: JobDependencies<'_>) -> Result<(), Box<dyn Error>> {
+    let JobDependencies {
+        db,
+        pubsub,
+        metadata_job_storage,
+        metadata_providers,
+        service_id_job_storage,
+        service_id_providers,
+        storage,
+        rate_limiter,
+    } = dependencies;
+    let pubsub_handle = PubSubHandle::from(pubsub).await;
     Monitor::new()
         .register(
             WorkerBuilder::new(metadata_job_storage.clone())
                 .layer(apalis::layers::RetryLayer::new(DefaultRetryPolicy))
                 .layer(apalis::layers::Extension(db.clone()))
+                .layer(apalis::layers::Extension(metadata_providers.clone()))
+                .layer(apalis::layers::Extension(CoverArtMirror::new(
+                    storage.clone(),
+                    rate_limiter.clone(),
+                )))
+                .layer(apalis::layers::Extension(pubsub_handle.clone()))
                 .build_fn(fetch_metadata),
         )
+        .register(
+            WorkerBuilder::new(service_id_job_storage.clone())
+                .layer(apalis::layers::RetryLayer::new(DefaultRetryPolicy))
+                .layer(apalis::layers::Extension(db.clone()))
+                .layer(apalis::layers::Extension(service_id_providers.clone()))
+                .layer(apalis::layers::Extension(pubsub_handle))
+                .build_fn(backfill_service_ids),
+        )
         .register(CronWorker::new(
             Schedule::from_str("0 * * * * * *").unwrap(),
             ServiceBuilder::new()
@@ -113,6 +312,21 @@ async fn set_up_job_monitor(
                 .layer(apalis::layers::Extension(metadata_job_storage.clone()))
                 .service(job_fn(schedule_fetch_metadata)),
         ))
+        .register(CronWorker::new(
+            Schedule::from_str("0 0 * * * * *").unwrap(),
+            ServiceBuilder::new()
+                .layer(apalis::layers::Extension(db.clone()))
+                .layer(apalis::layers::Extension(metadata_job_storage.clone()))
+                .service(job_fn(schedule_refresh_metadata)),
+        ))
+        .register(CronWorker::new(
+            Schedule::from_str("0 * * * * * *").unwrap(),
+            ServiceBuilder::new()
+                .layer(apalis::layers::Extension(db.clone()))
+                .layer(apalis::layers::Extension(service_id_job_storage.clone()))
+                .layer(apalis::layers::Extension(service_id_providers.clone()))
+                .service(job_fn(schedule_backfill_service_ids)),
+        ))
         .run()
         .await
         .map_err(|err| err.into())
@@ -148,12 +362,38 @@ async fn main() {
     dotenv().ok();
 
     let db_connection: DatabaseConnection = make_db_connection().await;
-    let redis: PubSub<RedisValue> = PubSub::connect(make_redis_connection().await).await;
+    let pubsub: PubSub<Event> = make_pubsub(&db_connection).await;
     let metadata_job_storage: RedisStorage<FetchMetadata> = make_metadata_job_storage().await;
+    let rate_limiter: RateLimiter = make_rate_limiter().await;
+    let catalog_urls: CatalogUrls = make_catalog_urls();
+    let metadata_providers: MetadataProviders =
+        make_metadata_providers(&rate_limiter, &catalog_urls);
+    let service_id_job_storage: RedisStorage<BackfillServiceIds> =
+        make_service_id_job_storage().await;
+    let service_id_providers: ServiceIdProviders =
+        make_service_id_providers(&rate_limiter, &catalog_urls);
+    let storage: Storage = make_storage();
 
-    let app: Router = set_up_app(&db_connection, &redis, &metadata_job_storage).await;
+    let app: Router = set_up_app(
+        &db_connection,
+        &pubsub,
+        &metadata_job_storage,
+        &service_id_job_storage,
+        &service_id_providers,
+        &storage,
+    )
+    .await;
 
-    let monitor = set_up_job_monitor(&db_connection, &metadata_job_storage);
+    let monitor = set_up_job_monitor(JobDependencies {
+        db: &db_connection,
+        pubsub: &pubsub,
+        metadata_job_storage: &metadata_job_storage,
+        metadata_providers: &metadata_providers,
+        service_id_job_storage: &service_id_job_storage,
+        service_id_providers: &service_id_providers,
+        storage: &storage,
+        rate_limiter: &rate_limiter,
+    });
     let server = start_server(app);
 
     future::try_join(monitor, server).await.unwrap();
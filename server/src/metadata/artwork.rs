@@ -0,0 +1,257 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::env;
+use std::io::Cursor;
+use std::str::FromStr;
+use std::time::Duration;
+
+use image::imageops::FilterType;
+use image::io::{Limits, Reader};
+use image::{DynamicImage, ImageOutputFormat};
+use log::info;
+use reqwest::Method;
+
+use crate::metadata::provider::ProviderResult;
+use crate::metadata::ratelimit::{LimitedClient, RateLimit, RateLimiter};
+use crate::storage::{Storage, StoredObject};
+
+const JPEG_QUALITY: u8 = 85;
+const JPEG_CONTENT_TYPE: &str = "image/jpeg";
+// Covers come from several hosts, none of which publishes a limit
+const DOWNLOAD_RATE_LIMIT: RateLimit = RateLimit {
+    capacity: 5,
+    per_second: 5.0,
+};
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_DOWNLOAD_BYTES: usize = 20 * 1024 * 1024;
+// Far beyond any real cover, but keeps a crafted header from allocating gigabytes
+const MAX_IMAGE_EDGE: u32 = 8192;
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CoverArtSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl CoverArtSize {
+    pub const ALL: [CoverArtSize; 3] = [
+        CoverArtSize::Small,
+        CoverArtSize::Medium,
+        CoverArtSize::Large,
+    ];
+
+    // Edge length of the bounding square
+    pub fn pixels(self) -> u32 {
+        match self {
+            CoverArtSize::Small => 128,
+            CoverArtSize::Medium => 320,
+            CoverArtSize::Large => 640,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CoverArtSize::Small => "small",
+            CoverArtSize::Medium => "medium",
+            CoverArtSize::Large => "large",
+        }
+    }
+}
+
+impl FromStr for CoverArtSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "small" => Ok(CoverArtSize::Small),
+            "medium" => Ok(CoverArtSize::Medium),
+            "large" => Ok(CoverArtSize::Large),
+            other => Err(format!("Unknown cover art size: {}", other)),
+        }
+    }
+}
+
+pub fn cover_art_key(isrc: &str, size: CoverArtSize) -> String {
+    format!("cover-art/{}/{}.jpg", isrc, size.as_str())
+}
+
+// Public URL of the route serving mirrored cover art
+pub fn cover_art_url(isrc: &str, size: CoverArtSize) -> String {
+    let public_url = env::var("PUBLIC_URL").unwrap_or("http://localhost:8080".to_owned());
+    format!(
+        "{}/cover-art/{}/{}",
+        public_url.trim_end_matches('/'),
+        isrc,
+        size.as_str()
+    )
+}
+
+// Downloads cover art once and stores it in all standard sizes
+#[derive(Clone)]
+pub struct CoverArtMirror {
+    storage: Storage,
+    client: LimitedClient,
+}
+
+impl CoverArtMirror {
+    pub fn new(storage: Storage, limiter: RateLimiter) -> Self {
+        Self {
+            storage,
+            client: LimitedClient::new(limiter, "coverart", DOWNLOAD_RATE_LIMIT),
+        }
+    }
+
+    pub async fn mirror(&self, isrc: &str, url: &str) -> ProviderResult<()> {
+        info!("Mirroring cover art for ISRC {} from {}", isrc, url);
+        let original = self.download(url).await?;
+
+        // Decoding and resampling is CPU-bound, so keep it off the async workers
+        let thumbnails = tokio::task::spawn_blocking(move || resize_all(&original)).await??;
+        for (size, bytes) in thumbnails {
+            self.storage
+                .put(
+                    &cover_art_key(isrc, size),
+                    StoredObject {
+                        content_type: JPEG_CONTENT_TYPE.to_owned(),
+                        bytes,
+                    },
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    // The timeout covers reading the body, which is given up on once it gets too large
+    async fn download(&self, url: &str) -> ProviderResult<Vec<u8>> {
+        let request = self
+            .client
+            .request(Method::GET, url)
+            .timeout(DOWNLOAD_TIMEOUT)
+            .build()?;
+        let mut response = self.client.execute(request).await?.error_for_status()?;
+        let too_large = || format!("Cover art larger than {} bytes", MAX_DOWNLOAD_BYTES);
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_DOWNLOAD_BYTES as u64)
+        {
+            return Err(too_large().into());
+        }
+        let mut original = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if original.len() + chunk.len() > MAX_DOWNLOAD_BYTES {
+                return Err(too_large().into());
+            }
+            original.extend_from_slice(&chunk);
+        }
+        Ok(original)
+    }
+}
+
+fn decode(original: &[u8]) -> ProviderResult<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_EDGE);
+    limits.max_image_height = Some(MAX_IMAGE_EDGE);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    let mut reader = Reader::new(Cursor::new(original)).with_guessed_format()?;
+    reader.limits(limits);
+    Ok(reader.decode()?)
+}
+
+fn resize_all(original: &[u8]) -> ProviderResult<Vec<(CoverArtSize, Vec<u8>)>> {
+    let image = decode(original)?;
+    let mut thumbnails = Vec::with_capacity(CoverArtSize::ALL.len());
+    for size in CoverArtSize::ALL {
+        // Keeps the aspect ratio, so non-square covers fit into the bounding square.
+        // Covers smaller than the square keep their size instead of being blown up
+        let fits = image.width() <= size.pixels() && image.height() <= size.pixels();
+        let resized = if fits {
+            image.to_rgb8()
+        } else {
+            image
+                .resize(size.pixels(), size.pixels(), FilterType::Lanczos3)
+                .into_rgb8()
+        };
+        // JPEG has no alpha channel
+        let resized = DynamicImage::ImageRgb8(resized);
+        let mut bytes = Cursor::new(Vec::new());
+        resized.write_to(&mut bytes, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+        thumbnails.push((size, bytes.into_inner()));
+    }
+    Ok(thumbnails)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Rgb};
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(width, height, Rgb([0, 0, 0])));
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    fn dimensions(thumbnails: &[(CoverArtSize, Vec<u8>)]) -> Vec<(CoverArtSize, (u32, u32))> {
+        thumbnails
+            .iter()
+            .map(|(size, bytes)| {
+                let image = image::load_from_memory(bytes).unwrap();
+                (*size, (image.width(), image.height()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn downscales_into_bounding_square() {
+        let thumbnails = resize_all(&png(1000, 500)).unwrap();
+        assert_eq!(
+            dimensions(&thumbnails),
+            vec![
+                (CoverArtSize::Small, (128, 64)),
+                (CoverArtSize::Medium, (320, 160)),
+                (CoverArtSize::Large, (640, 320)),
+            ]
+        );
+    }
+
+    #[test]
+    fn never_upscales() {
+        let thumbnails = resize_all(&png(200, 200)).unwrap();
+        assert_eq!(
+            dimensions(&thumbnails),
+            vec![
+                (CoverArtSize::Small, (128, 128)),
+                (CoverArtSize::Medium, (200, 200)),
+                (CoverArtSize::Large, (200, 200)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_images() {
+        assert!(resize_all(&png(MAX_IMAGE_EDGE + 1, 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_non_images() {
+        assert!(resize_all(b"<html></html>").is_err());
+    }
+}
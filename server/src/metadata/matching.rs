@@ -0,0 +1,362 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+
+use entity::isrc_metadata;
+
+use crate::metadata::provider::CatalogTrack;
+
+// Written to isrc_services right away, if the lengths are known to match
+const ACCEPT_CONFIDENCE: f32 = 0.9;
+// Kept for review, anything below is dropped
+pub const FLAG_CONFIDENCE: f32 = 0.6;
+
+const NAME_WEIGHT: f32 = 0.5;
+const ARTIST_WEIGHT: f32 = 0.3;
+const LENGTH_WEIGHT: f32 = 0.2;
+// Lengths further apart than this do not count as the same recording at all
+const LENGTH_TOLERANCE_MS: f32 = 10_000.0;
+// Neither speaks for nor against a match
+const UNKNOWN_LENGTH_SIMILARITY: f32 = 0.5;
+// Suffixes with these words name a different recording, e.g. "(Live at Wembley)" or
+// "- Acoustic Version", and are kept in the name
+const VERSION_QUALIFIERS: [&str; 11] = [
+    "live",
+    "remix",
+    "mix",
+    "acoustic",
+    "instrumental",
+    "demo",
+    "unplugged",
+    "karaoke",
+    "acapella",
+    "orchestral",
+    "rehearsal",
+];
+
+#[derive(Debug, Clone)]
+pub struct TrackMatch {
+    pub track: CatalogTrack,
+    // Between 0 and 1
+    pub confidence: f32,
+    // Both lengths are known and within tolerance
+    pub length_matches: bool,
+}
+
+impl TrackMatch {
+    // Whether the match can be written without review. A name and artist match alone is not
+    // enough, as singles, live and album versions often share both
+    pub fn is_accepted(&self) -> bool {
+        self.confidence >= ACCEPT_CONFIDENCE && self.length_matches
+    }
+}
+
+// The most similar track to the stored metadata, skipping tracks that were rejected before
+pub fn best_match(
+    metadata: &isrc_metadata::Model,
+    candidates: Vec<CatalogTrack>,
+    rejected_service_ids: &HashSet<String>,
+) -> Option<TrackMatch> {
+    candidates
+        .into_iter()
+        .filter(|track| !rejected_service_ids.contains(&track.service_id))
+        .map(|track| TrackMatch {
+            confidence: confidence(metadata, &track),
+            length_matches: length_difference(metadata.length_ms, track.length_ms)
+                .is_some_and(|difference| difference < LENGTH_TOLERANCE_MS),
+            track,
+        })
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+}
+
+pub fn confidence(metadata: &isrc_metadata::Model, track: &CatalogTrack) -> f32 {
+    let name = similarity(
+        &normalize_name(&metadata.name),
+        &normalize_name(&track.name),
+    );
+    let artist = token_similarity(
+        &artist_tokens(&metadata.artist),
+        &artist_tokens(&track.artist),
+    );
+    let length = match length_difference(metadata.length_ms, track.length_ms) {
+        Some(difference) => 1.0 - (difference / LENGTH_TOLERANCE_MS).min(1.0),
+        None => UNKNOWN_LENGTH_SIMILARITY,
+    };
+    NAME_WEIGHT * name + ARTIST_WEIGHT * artist + LENGTH_WEIGHT * length
+}
+
+fn length_difference(a: Option<i32>, b: Option<i32>) -> Option<f32> {
+    Some((a? - b?).abs() as f32)
+}
+
+// Drops suffixes like "(Remastered 2011)", "[Mono]" or "- Single Edit", which differ between
+// reissues of the same recording, but keeps version qualifiers like "(Live)" or "- Remix"
+fn normalize_name(name: &str) -> String {
+    let end = [" (", " [", " - "]
+        .iter()
+        .filter_map(|separator| name.find(separator))
+        .min()
+        .unwrap_or(name.len());
+    let suffixes = name[end..].replace(" - ", "(");
+    let versions = suffixes
+        .split(['(', ')', '[', ']'])
+        .map(normalize)
+        .filter(|suffix| {
+            suffix
+                .split(' ')
+                .any(|word| VERSION_QUALIFIERS.contains(&word))
+        });
+    std::iter::once(normalize(&name[..end]))
+        .chain(versions)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn artist_tokens(artist: &str) -> HashSet<String> {
+    normalize(artist)
+        .split(' ')
+        .filter(|token| !["and", "feat", "ft", "featuring", "with", "x"].contains(token))
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_owned())
+        .collect()
+}
+
+// Lowercase words, without punctuation
+fn normalize(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_lowercase().next().unwrap_or(c)
+            } else {
+                ' '
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn token_similarity(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    a.intersection(b).count() as f32 / a.union(b).count() as f32
+}
+
+// 1 minus the edit distance, relative to the longer string
+fn similarity(a: &str, b: &str) -> f32 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    1.0 - previous[b.len()] as f32 / longest as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(name: &str, artist: &str, length_ms: Option<i32>) -> isrc_metadata::Model {
+        isrc_metadata::Model {
+            isrc: "ZZMTF2200001".to_owned(),
+            name: name.to_owned(),
+            artist: artist.to_owned(),
+            cover_art_url: None,
+            release_title: None,
+            release_date: None,
+            length_ms,
+            recording_mbid: None,
+            release_mbid: None,
+            cover_art_mirrored_at: None,
+        }
+    }
+
+    fn track(service_id: &str, name: &str, artist: &str, length_ms: Option<i32>) -> CatalogTrack {
+        CatalogTrack {
+            service_id: service_id.to_owned(),
+            name: name.to_owned(),
+            artist: artist.to_owned(),
+            length_ms,
+            isrc: None,
+        }
+    }
+
+    #[test]
+    fn similarity_of_edits() {
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity("song", "song"), 1.0);
+        assert_eq!(similarity("song", ""), 0.0);
+        assert_eq!(similarity("abcd", "wxyz"), 0.0);
+        assert_eq!(similarity("song", "sing"), 0.75);
+        assert_eq!(similarity("song", "songs"), 0.8);
+        assert_eq!(similarity("kitten", "sitting"), 1.0 - 3.0 / 7.0);
+        assert_eq!(similarity("süß", "suß"), 1.0 - 1.0 / 3.0);
+    }
+
+    #[test]
+    fn normalize_name_drops_reissue_suffixes() {
+        assert_eq!(normalize_name("Song"), "song");
+        assert_eq!(normalize_name("Don't Stop Me Now!"), "don t stop me now");
+        assert_eq!(normalize_name("Song (Remastered 2011)"), "song");
+        assert_eq!(normalize_name("Song [Mono]"), "song");
+        assert_eq!(normalize_name("Song - Single Edit"), "song");
+        assert_eq!(normalize_name("Song (feat. Someone)"), "song");
+    }
+
+    #[test]
+    fn normalize_name_keeps_version_qualifiers() {
+        assert_eq!(normalize_name("Song (Live)"), "song live");
+        assert_eq!(normalize_name("Song - Live"), "song live");
+        assert_eq!(
+            normalize_name("Song [Acoustic Version]"),
+            "song acoustic version"
+        );
+        assert_eq!(
+            normalize_name("Song - Someone Remix [Remastered]"),
+            "song someone remix"
+        );
+        assert_eq!(
+            normalize_name("Song (feat. Someone) [Live at Wembley]"),
+            "song live at wembley"
+        );
+        assert_ne!(
+            normalize_name("Song (Live)"),
+            normalize_name("Song (Remastered)")
+        );
+    }
+
+    #[test]
+    fn confidence_of_exact_match() {
+        let metadata = metadata("Song", "Artist", Some(200_000));
+        assert_eq!(
+            confidence(&metadata, &track("1", "Song", "Artist", Some(200_000))),
+            1.0
+        );
+        // Remasters match, features are left to the artist
+        assert_eq!(
+            confidence(
+                &metadata,
+                &track("1", "Song - Remastered 2011", "artist", Some(200_000))
+            ),
+            1.0
+        );
+    }
+
+    #[test]
+    fn confidence_by_length() {
+        let metadata = metadata("Song", "Artist", Some(200_000));
+        let close = confidence(&metadata, &track("1", "Song", "Artist", Some(202_000)));
+        let far = confidence(&metadata, &track("1", "Song", "Artist", Some(260_000)));
+        let unknown = confidence(&metadata, &track("1", "Song", "Artist", None));
+        assert!((close - 0.96).abs() < 1e-6);
+        assert!((far - 0.8).abs() < 1e-6);
+        assert!((unknown - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn confidence_by_artist() {
+        let metadata = metadata("Song", "Artist & Band", Some(200_000));
+        let reordered = confidence(
+            &metadata,
+            &track("1", "Song", "Band and Artist", Some(200_000)),
+        );
+        let partial = confidence(&metadata, &track("1", "Song", "Artist", Some(200_000)));
+        let other = confidence(&metadata, &track("1", "Song", "Someone", Some(200_000)));
+        assert_eq!(reordered, 1.0);
+        assert!((partial - 0.85).abs() < 1e-6);
+        assert!((other - 0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn best_match_picks_most_similar() {
+        let metadata = metadata("Song", "Artist", Some(200_000));
+        let candidates = vec![
+            track("live", "Song (Live)", "Artist", Some(200_000)),
+            track("studio", "Song", "Artist", Some(201_000)),
+            track("other", "Other Song", "Artist", Some(200_000)),
+        ];
+        let best = best_match(&metadata, candidates, &HashSet::new()).unwrap();
+        assert_eq!(best.track.service_id, "studio");
+        assert!(best.length_matches);
+        assert!(best.is_accepted());
+    }
+
+    #[test]
+    fn best_match_skips_rejected() {
+        let metadata = metadata("Song", "Artist", Some(200_000));
+        let candidates = vec![
+            track("rejected", "Song", "Artist", Some(200_000)),
+            track("live", "Song (Live)", "Artist", Some(200_000)),
+        ];
+        let rejected = HashSet::from(["rejected".to_owned()]);
+        let best = best_match(&metadata, candidates.clone(), &rejected).unwrap();
+        assert_eq!(best.track.service_id, "live");
+        assert!(!best.is_accepted());
+
+        let rejected = HashSet::from(["rejected".to_owned(), "live".to_owned()]);
+        assert!(best_match(&metadata, candidates, &rejected).is_none());
+        assert!(best_match(&metadata, vec![], &HashSet::new()).is_none());
+    }
+
+    #[test]
+    fn best_match_without_known_length_is_not_accepted() {
+        let candidates = vec![track("1", "Song", "Artist", Some(200_000))];
+        let best = best_match(
+            &metadata("Song", "Artist", None),
+            candidates,
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert!(best.confidence >= FLAG_CONFIDENCE);
+        assert!(!best.length_matches);
+        assert!(!best.is_accepted());
+
+        let candidates = vec![track("1", "Song", "Artist", None)];
+        let best = best_match(
+            &metadata("Song", "Artist", Some(200_000)),
+            candidates,
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert!(!best.is_accepted());
+    }
+
+    #[test]
+    fn best_match_out_of_tolerance_is_not_accepted() {
+        let candidates = vec![track("1", "Song", "Artist", Some(215_000))];
+        let best = best_match(
+            &metadata("Song", "Artist", Some(200_000)),
+            candidates,
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert!(!best.length_matches);
+        assert!(!best.is_accepted());
+    }
+}
@@ -14,35 +14,63 @@
  * limitations under the License.
  */
 
-use std::error::Error;
-use std::time::Duration;
-
 use apalis::prelude::{Job, JobContext, JobError, JobResult, Storage};
 use apalis::redis::RedisStorage;
-use axum::http::Method;
-use chrono::{FixedOffset, Utc};
-use fred::bytes::Buf;
-use log::{error, info};
-use reqwest::header::{ACCEPT, USER_AGENT};
-use reqwest::StatusCode;
+use std::env;
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use itertools::Itertools;
+use log::{error, info, warn};
+use sea_orm::sea_query::{Expr, OnConflict};
 use sea_orm::ActiveValue::Set;
 use sea_orm::IdenStatic;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseConnection, DeriveColumn, EntityTrait, EnumIter,
-    JoinType, QueryFilter, QuerySelect, TransactionTrait,
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr, DeriveColumn, EntityTrait,
+    EnumIter, JoinType, QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
-use serde_xml_rs::from_reader;
-use tokio::time::sleep;
+use uuid::Uuid;
+
+use entity::sea_orm_active_enums::MetadataState;
+use entity::{
+    artists, isrc_metadata, isrc_metadata_artist_credits, isrc_metadata_genres,
+    isrc_metadata_status, motifs,
+};
 
-use entity::{isrc_metadata, isrc_metadata_status, motifs};
+use crate::domain::common::typedef::Isrc;
+use crate::domain::motif::pubsub::topic_metadata_updated;
+use crate::metadata::artwork::CoverArtMirror;
+use crate::metadata::provider::{MetadataProviders, TrackMetadata};
+use crate::metadata::status::FetchOutcome;
+use crate::pubsub::event::{Event, EventPayload};
+use crate::PubSubHandle;
 
+pub mod artwork;
 mod coverartarchive;
+pub mod matching;
 mod musicbrainz;
-
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub mod provider;
+pub mod ratelimit;
+pub mod service_ids;
+pub mod status;
+#[cfg(any(test, feature = "catalog-stub"))]
+pub mod stub;
+#[cfg(test)]
+mod tests;
+
+const DEFAULT_REFRESH_AFTER_DAYS: i64 = 30;
+// Spreads refreshes over several runs instead of hitting providers with all ISRCs at once
+const REFRESH_BATCH_SIZE: u64 = 100;
+// Rows are marked pending before their jobs are pushed, so a failed push or a lost
+// job leaves them pending until the scheduler picks them up again after this long
+const PENDING_TIMEOUT_MINS: i64 = 60;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FetchMetadata {
-    pub isrc: String,
+    pub isrc: Isrc,
+    // Re-fetch metadata that has already been fetched
+    #[serde(default)]
+    pub refresh: bool,
 }
 
 impl Job for FetchMetadata {
@@ -56,14 +84,28 @@ impl Job for ScheduleFetchMetadata {
     const NAME: &'static str = "motif::ScheduleFetchMetadata";
 }
 
-const MUSICBRAINZ_BASE_URL: &str = "https://musicbrainz.org/ws/2/";
-const COVER_ART_ARCHIVE_BASE_URL: &str = "https://coverartarchive.org/release/";
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct ScheduleRefreshMetadata {}
+
+impl Job for ScheduleRefreshMetadata {
+    const NAME: &'static str = "motif::ScheduleRefreshMetadata";
+}
+
+fn refresh_after() -> Duration {
+    let days = env::var("METADATA_REFRESH_AFTER_DAYS")
+        .map(|days| {
+            days.parse::<i64>()
+                .expect("METADATA_REFRESH_AFTER_DAYS must be a number")
+        })
+        .unwrap_or(DEFAULT_REFRESH_AFTER_DAYS);
+    Duration::days(days)
+}
 
 pub async fn schedule_fetch_metadata(
     _schedule: ScheduleFetchMetadata,
     ctx: JobContext,
 ) -> Result<JobResult, JobError> {
-    info!("Scheduling metadata fetch for ISRCs without status");
+    info!("Scheduling metadata fetch for ISRCs without status, due for retry or stuck pending");
 
     let db: &DatabaseConnection = ctx.data_opt().unwrap();
     let mut storage: RedisStorage<FetchMetadata> = ctx
@@ -76,10 +118,11 @@ pub async fn schedule_fetch_metadata(
         Isrc,
     }
 
+    let now = Utc::now().with_timezone(&FixedOffset::east(0));
     let isrcs_to_fetch = db
-        .transaction::<_, Vec<String>, JobError>(|txn| {
+        .transaction::<_, Vec<Isrc>, DbErr>(|txn| {
             Box::pin(async move {
-                let isrcs_to_schedule: Vec<String> = motifs::Entity::find()
+                let mut isrcs_to_schedule: Vec<String> = motifs::Entity::find()
                     .select_only()
                     .distinct()
                     .column_as(motifs::Column::Isrc, QueryAs::Isrc)
@@ -93,161 +136,533 @@ pub async fn schedule_fetch_metadata(
                     .filter(isrc_metadata_status::Column::Isrc.is_null())
                     .into_values::<_, QueryAs>()
                     .all(txn)
-                    .await
-                    .unwrap();
-
-                for isrc in &isrcs_to_schedule {
-                    isrc_metadata_status::ActiveModel {
-                        isrc: Set(isrc.clone()),
-                        updated_at: Set(Utc::now().with_timezone(&FixedOffset::east(0))),
-                    }
-                    .insert(txn)
-                    .await
-                    .unwrap();
-                }
-
-                Ok(isrcs_to_schedule)
+                    .await?;
+
+                isrcs_to_schedule.extend(isrcs_due_for_retry(txn, now).await?);
+
+                mark_pending_valid(txn, isrcs_to_schedule).await
             })
         })
         .await
-        .unwrap();
+        .map_err(|err| JobError::Failed(Box::new(err)))?;
 
     for isrc in &isrcs_to_fetch {
-        storage.push(FetchMetadata { isrc: isrc.clone() }).await?;
+        storage
+            .push(FetchMetadata {
+                isrc: isrc.clone(),
+                refresh: false,
+            })
+            .await?;
     }
 
     if !isrcs_to_fetch.is_empty() {
         info!(
             "Scheduled metadata fetch for ISRCs: {}",
-            isrcs_to_fetch.join(", ")
+            isrcs_to_fetch.iter().join(", ")
+        );
+    } else {
+        info!("Nothing to schedule: All ISRCs have a metadata status and none is due");
+    }
+
+    Ok(JobResult::Success)
+}
+
+// Failed and not found ISRCs past their next retry, and pending ones whose job got lost
+async fn isrcs_due_for_retry<C: ConnectionTrait>(
+    db: &C,
+    now: DateTime<FixedOffset>,
+) -> Result<Vec<String>, DbErr> {
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+    enum QueryAs {
+        Isrc,
+    }
+
+    let due_for_retry = Condition::all()
+        .add(
+            isrc_metadata_status::Column::State
+                .is_in([MetadataState::Failed, MetadataState::NotFound]),
+        )
+        .add(
+            Condition::any()
+                .add(isrc_metadata_status::Column::NextRetryAt.lte(now))
+                // Not found before retries were scheduled for them
+                .add(isrc_metadata_status::Column::NextRetryAt.is_null()),
+        );
+    let stuck_pending = Condition::all()
+        .add(isrc_metadata_status::Column::State.eq(MetadataState::Pending))
+        .add(
+            isrc_metadata_status::Column::UpdatedAt
+                .lte(now - Duration::minutes(PENDING_TIMEOUT_MINS)),
+        );
+    isrc_metadata_status::Entity::find()
+        .select_only()
+        .column_as(isrc_metadata_status::Column::Isrc, QueryAs::Isrc)
+        .filter(Condition::any().add(due_for_retry).add(stuck_pending))
+        .into_values::<_, QueryAs>()
+        .all(db)
+        .await
+}
+
+pub async fn schedule_refresh_metadata(
+    _schedule: ScheduleRefreshMetadata,
+    ctx: JobContext,
+) -> Result<JobResult, JobError> {
+    let refresh_after = refresh_after();
+    info!(
+        "Scheduling metadata refresh for ISRCs fetched more than {} days ago",
+        refresh_after.num_days()
+    );
+
+    let db: &DatabaseConnection = ctx.data_opt().unwrap();
+    let mut storage: RedisStorage<FetchMetadata> = ctx
+        .data_opt::<RedisStorage<FetchMetadata>>()
+        .unwrap()
+        .clone();
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+    enum QueryAs {
+        Isrc,
+    }
+
+    let stale_before = Utc::now().with_timezone(&FixedOffset::east(0)) - refresh_after;
+    let isrcs_to_refresh = db
+        .transaction::<_, Vec<Isrc>, DbErr>(|txn| {
+            Box::pin(async move {
+                let isrcs: Vec<String> = isrc_metadata_status::Entity::find()
+                    .select_only()
+                    .column_as(isrc_metadata_status::Column::Isrc, QueryAs::Isrc)
+                    .filter(isrc_metadata_status::Column::State.eq(MetadataState::Fetched))
+                    .filter(isrc_metadata_status::Column::Manual.eq(false))
+                    .filter(isrc_metadata_status::Column::UpdatedAt.lte(stale_before))
+                    .order_by_asc(isrc_metadata_status::Column::UpdatedAt)
+                    .limit(REFRESH_BATCH_SIZE)
+                    .into_values::<_, QueryAs>()
+                    .all(txn)
+                    .await?;
+
+                // No longer FETCHED, so that the next run does not pick them up again
+                mark_pending_valid(txn, isrcs).await
+            })
+        })
+        .await
+        .map_err(|err| JobError::Failed(Box::new(err)))?;
+
+    for isrc in &isrcs_to_refresh {
+        storage
+            .push(FetchMetadata {
+                isrc: isrc.clone(),
+                refresh: true,
+            })
+            .await?;
+    }
+
+    if !isrcs_to_refresh.is_empty() {
+        info!(
+            "Scheduled metadata refresh for ISRCs: {}",
+            isrcs_to_refresh.iter().join(", ")
         );
     } else {
-        info!("Nothing to schedule: All ISRCs have a metadata status");
+        info!("Nothing to refresh: All metadata is up to date");
     }
 
     Ok(JobResult::Success)
 }
 
+// Rows from before ISRCs were validated may hold ones that no provider will ever know
+async fn mark_pending_valid<C: ConnectionTrait>(
+    db: &C,
+    isrcs: Vec<String>,
+) -> Result<Vec<Isrc>, DbErr> {
+    let mut valid = Vec::with_capacity(isrcs.len());
+    for isrc in isrcs {
+        status::mark_pending(db, isrc.clone()).await?;
+        match isrc.parse::<Isrc>() {
+            Ok(parsed) => valid.push(parsed),
+            Err(err) => {
+                warn!("Not fetching metadata for ISRC {}: {}", isrc, err);
+                status::record_outcome(db, isrc, FetchOutcome::NotFound).await?;
+            }
+        }
+    }
+    Ok(valid)
+}
+
+// What fetching takes from the job context. Cover art is not mirrored without a mirror,
+// and updates are not published without pubsub
+struct FetchDependencies<'a> {
+    db: &'a DatabaseConnection,
+    providers: &'a MetadataProviders,
+    mirror: Option<&'a CoverArtMirror>,
+    pubsub: Option<&'a PubSubHandle<Event>>,
+}
+
 pub async fn fetch_metadata(
     metadata: FetchMetadata,
     ctx: JobContext,
+) -> Result<JobResult, JobError> {
+    let dependencies = FetchDependencies {
+        db: ctx.data_opt().unwrap(),
+        providers: ctx.data_opt().unwrap(),
+        mirror: ctx.data_opt(),
+        pubsub: ctx.data_opt(),
+    };
+    fetch_and_store_metadata(&dependencies, metadata).await
+}
+
+async fn fetch_and_store_metadata(
+    dependencies: &FetchDependencies<'_>,
+    metadata: FetchMetadata,
 ) -> Result<JobResult, JobError> {
     info!("Fetching metadata for ISRC: {}", &metadata.isrc);
+    let isrc = metadata.isrc.to_string();
 
-    // Sleep 1s to avoid MusicBrainz' per-IP rate limit
-    sleep(Duration::from_secs(1)).await;
+    let db = dependencies.db;
+    let providers = dependencies.providers;
 
-    let db: &DatabaseConnection = ctx.data_opt().unwrap();
-    let client = reqwest::Client::new();
+    let current = isrc_metadata_status::Entity::find_by_id(isrc.clone())
+        .one(db)
+        .await
+        .map_err(|err| JobError::Failed(Box::new(err)))?;
+    let pinned_recording_mbid = match current {
+        // Manually set metadata is never overwritten, unless an admin asks for a re-fetch.
+        // Its cover art is mirrored all the same
+        Some(current) if current.manual => {
+            info!("Metadata for ISRC {} was set manually", &metadata.isrc);
+            mirror_manual_cover_art(dependencies, isrc)
+                .await
+                .map_err(|err| JobError::Failed(Box::new(err)))?;
+            return Ok(JobResult::Success);
+        }
+        Some(current) if current.state == MetadataState::Fetched && !metadata.refresh => {
+            info!("Metadata for ISRC {} already fetched", &metadata.isrc);
+            return Ok(JobResult::Success);
+        }
+        Some(current) => current.pinned_recording_mbid.map(|mbid| mbid.to_string()),
+        // Jobs pushed on motif creation precede the scheduler
+        None => {
+            status::mark_pending(db, isrc.clone())
+                .await
+                .map_err(|err| JobError::Failed(Box::new(err)))?;
+            None
+        }
+    };
 
-    // Fetch general metadata from MusicBrainz
-    let mb_metadata = musicbrainz_isrc_lookup(&client, &metadata.isrc)
+    // Failures are recorded and retried with backoff by the scheduler
+    let track_metadata = match providers
+        .lookup(metadata.isrc.as_str(), pinned_recording_mbid.as_deref())
         .await
-        .map_err(|err| JobError::Failed(err))?;
+    {
+        Ok(track_metadata) => track_metadata,
+        Err(err) => {
+            error!(
+                "Metadata lookup for ISRC {} failed: {}",
+                &metadata.isrc, err
+            );
+            status::record_outcome(db, isrc.clone(), FetchOutcome::Failed(err.to_string()))
+                .await
+                .map_err(|err| JobError::Failed(Box::new(err)))?;
+            return Ok(JobResult::Success);
+        }
+    };
 
-    if mb_metadata.is_none() {
-        info!("Did not find metadata for ISRC: {}", &metadata.isrc);
+    if track_metadata.is_none() {
+        // Keep serving what was fetched before, rather than dropping the ISRC from refreshes
+        let outcome = if metadata.refresh {
+            warn!("Metadata for ISRC {} is gone upstream", &metadata.isrc);
+            FetchOutcome::Fetched
+        } else {
+            info!("Did not find metadata for ISRC: {}", &metadata.isrc);
+            FetchOutcome::NotFound
+        };
+        status::record_outcome(db, isrc.clone(), outcome)
+            .await
+            .map_err(|err| JobError::Failed(Box::new(err)))?;
         return Ok(JobResult::Success);
     }
-    let mb_metadata = mb_metadata.unwrap();
+    let track_metadata = track_metadata.unwrap();
 
-    // Fetch cover art url from CoverArtArchive
-    let cover_url = cover_art_archive_lookup(&client, &mb_metadata.release_mbids)
+    // Mirrored sizes stay valid as long as the upstream cover art does not change
+    let previous = find_stored_metadata(db, isrc.clone())
         .await
-        .map_err(|err| JobError::Failed(err))?;
-
-    let model = isrc_metadata::ActiveModel {
-        isrc: Set(metadata.isrc.clone()),
-        name: Set(mb_metadata.name),
-        artist: Set(mb_metadata.artist),
-        cover_art_url: Set(cover_url),
+        .map_err(|err| JobError::Failed(Box::new(err)))?;
+    let mut changed = previous
+        .as_ref()
+        .map_or(true, |previous| has_changed(previous, &track_metadata));
+    let mirrored_at = previous
+        .map(|previous| previous.metadata)
+        .filter(|previous| previous.cover_art_url == track_metadata.cover_art_url)
+        .and_then(|previous| previous.cover_art_mirrored_at);
+    let cover_art_to_mirror = match mirrored_at {
+        Some(_) => None,
+        None => track_metadata.cover_art_url.clone(),
     };
 
-    match model.insert(db).await {
-        Ok(_) => {
-            info!("Successfully updated metadata for ISRC: {}", &metadata.isrc);
-            Ok(JobResult::Success)
-        }
-        Err(err) => {
-            error!("Job failed: DB error: {}", err);
-            Err(JobError::Unknown)
+    let txn_isrc = isrc.clone();
+    let match_reason = track_metadata.match_reason.clone();
+    let result = db
+        .transaction::<_, (), DbErr>(|txn| {
+            Box::pin(async move {
+                store_metadata(txn, txn_isrc.clone(), track_metadata, mirrored_at).await?;
+                status::record_outcome(txn, txn_isrc.clone(), FetchOutcome::Fetched).await?;
+                status::record_match_reason(txn, txn_isrc, match_reason).await
+            })
+        })
+        .await;
+
+    if let Err(err) = result {
+        error!("Job failed: DB error: {}", err);
+        return Err(JobError::Unknown);
+    }
+    info!("Successfully updated metadata for ISRC: {}", &metadata.isrc);
+
+    // Without a mirror, clients fall back to the upstream URL
+    if let (Some(url), Some(mirror)) = (cover_art_to_mirror, dependencies.mirror) {
+        changed |= mirror_cover_art(db, mirror, isrc.clone(), &url)
+            .await
+            .map_err(|err| JobError::Failed(Box::new(err)))?;
+    }
+
+    if changed {
+        if let Some(pubsub) = dependencies.pubsub {
+            pubsub
+                .publish(
+                    topic_metadata_updated(metadata.isrc.as_str()),
+                    Event::system(EventPayload::MetadataUpdated { isrc: isrc.clone() }),
+                )
+                .await;
         }
     }
+    Ok(JobResult::Success)
 }
 
-struct MusicBrainzMetadata {
-    mbid: String,
-    name: String,
-    artist: String,
-    release_mbids: Vec<String>,
+async fn mirror_manual_cover_art(
+    dependencies: &FetchDependencies<'_>,
+    isrc: String,
+) -> Result<(), DbErr> {
+    let db = dependencies.db;
+    let url = match isrc_metadata::Entity::find_by_id(isrc.clone())
+        .one(db)
+        .await?
+        .filter(|current| current.cover_art_mirrored_at.is_none())
+        .and_then(|current| current.cover_art_url)
+    {
+        Some(url) => url,
+        None => return Ok(()),
+    };
+    let mirror = match dependencies.mirror {
+        Some(mirror) => mirror,
+        None => return Ok(()),
+    };
+    if mirror_cover_art(db, mirror, isrc.clone(), &url).await? {
+        if let Some(pubsub) = dependencies.pubsub {
+            pubsub
+                .publish(
+                    topic_metadata_updated(&isrc),
+                    Event::system(EventPayload::MetadataUpdated { isrc: isrc.clone() }),
+                )
+                .await;
+        }
+    }
+    Ok(())
 }
 
-async fn musicbrainz_isrc_lookup(
-    client: &reqwest::Client,
-    isrc: &String,
-) -> Result<Option<MusicBrainzMetadata>, Box<dyn Error + Send + Sync>> {
-    let request = client
-        .request(
-            Method::GET,
-            format!("{}isrc/{}", MUSICBRAINZ_BASE_URL, isrc),
+// Upserts the metadata row and credited artists, and replaces its artist credits and genres
+pub async fn store_metadata<C: ConnectionTrait>(
+    db: &C,
+    isrc: String,
+    track_metadata: TrackMetadata,
+    cover_art_mirrored_at: Option<DateTime<FixedOffset>>,
+) -> Result<(), DbErr> {
+    let model = isrc_metadata::ActiveModel {
+        isrc: Set(isrc.clone()),
+        name: Set(track_metadata.name),
+        artist: Set(track_metadata.artist),
+        cover_art_url: Set(track_metadata.cover_art_url),
+        release_title: Set(track_metadata.release_title),
+        release_date: Set(track_metadata.release_date),
+        length_ms: Set(track_metadata.length_ms),
+        recording_mbid: Set(parse_mbid(track_metadata.recording_mbid)),
+        release_mbid: Set(parse_mbid(track_metadata.release_mbid)),
+        cover_art_mirrored_at: Set(cover_art_mirrored_at),
+    };
+    let now = Utc::now().with_timezone(&FixedOffset::east(0));
+    let artist_models: Vec<artists::ActiveModel> = track_metadata
+        .artist_credits
+        .iter()
+        .filter_map(|credit| credit.artist.as_ref())
+        .filter_map(|artist| {
+            parse_mbid(Some(artist.mbid.clone())).map(|mbid| (mbid, artist.name.clone()))
+        })
+        // An upsert must not touch the same row twice
+        .unique_by(|(mbid, _)| *mbid)
+        .map(|(mbid, name)| artists::ActiveModel {
+            mbid: Set(mbid),
+            name: Set(name),
+            updated_at: Set(now),
+        })
+        .collect();
+    let genres: Vec<isrc_metadata_genres::ActiveModel> = track_metadata
+        .genres
+        .into_iter()
+        .enumerate()
+        .map(|(position, genre)| isrc_metadata_genres::ActiveModel {
+            isrc: Set(isrc.clone()),
+            genre: Set(genre),
+            position: Set(position as i32),
+        })
+        .collect();
+    let credits: Vec<isrc_metadata_artist_credits::ActiveModel> = track_metadata
+        .artist_credits
+        .into_iter()
+        .enumerate()
+        .map(
+            |(position, credit)| isrc_metadata_artist_credits::ActiveModel {
+                isrc: Set(isrc.clone()),
+                position: Set(position as i32),
+                name: Set(credit.name),
+                join_phrase: Set(credit.join_phrase),
+                artist_mbid: Set(parse_mbid(credit.artist.map(|artist| artist.mbid))),
+            },
         )
-        .query(&[("inc", "artists+releases")])
-        .header(ACCEPT, "application/xml")
-        .header(USER_AGENT, "de.julianostarek.motif")
-        .build()
-        .unwrap();
-
-    let response = client.execute(request).await?;
-    if response.status() == StatusCode::NOT_FOUND {
-        return Ok(None);
-    }
-
-    let metadata_response: musicbrainz::Metadata = from_reader(response.bytes().await?.reader())?;
-    let recording = metadata_response
-        .isrc
-        .recording_list
-        .recording
-        .first()
-        .unwrap();
-    let metadata = MusicBrainzMetadata {
-        mbid: recording.id.clone(),
-        name: recording.title.clone(),
-        artist: recording
-            .artist_credit
-            .name_credit
-            .first()
-            .unwrap()
-            .artist
-            .name
-            .clone(),
-        release_mbids: recording
-            .release_list
-            .release
-            .iter()
-            .map(|release| release.id.clone())
-            .collect(),
+        .collect();
+
+    isrc_metadata::Entity::insert(model)
+        .on_conflict(
+            OnConflict::column(isrc_metadata::Column::Isrc)
+                .update_columns([
+                    isrc_metadata::Column::Name,
+                    isrc_metadata::Column::Artist,
+                    isrc_metadata::Column::CoverArtUrl,
+                    isrc_metadata::Column::ReleaseTitle,
+                    isrc_metadata::Column::ReleaseDate,
+                    isrc_metadata::Column::LengthMs,
+                    isrc_metadata::Column::RecordingMbid,
+                    isrc_metadata::Column::ReleaseMbid,
+                    isrc_metadata::Column::CoverArtMirroredAt,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+    if !artist_models.is_empty() {
+        artists::Entity::insert_many(artist_models)
+            .on_conflict(
+                OnConflict::column(artists::Column::Mbid)
+                    .update_columns([artists::Column::Name, artists::Column::UpdatedAt])
+                    .to_owned(),
+            )
+            .exec(db)
+            .await?;
+    }
+    // Credits of a previous fetch are replaced as a whole
+    isrc_metadata_artist_credits::Entity::delete_many()
+        .filter(isrc_metadata_artist_credits::Column::Isrc.eq(isrc.clone()))
+        .exec(db)
+        .await?;
+    if !credits.is_empty() {
+        isrc_metadata_artist_credits::Entity::insert_many(credits)
+            .exec(db)
+            .await?;
+    }
+    isrc_metadata_genres::Entity::delete_many()
+        .filter(isrc_metadata_genres::Column::Isrc.eq(isrc))
+        .exec(db)
+        .await?;
+    if !genres.is_empty() {
+        isrc_metadata_genres::Entity::insert_many(genres)
+            .exec(db)
+            .await?;
+    }
+    Ok(())
+}
+
+// What a previous fetch stored for an ISRC
+struct StoredMetadata {
+    metadata: isrc_metadata::Model,
+    credits: Vec<isrc_metadata_artist_credits::Model>,
+    genres: Vec<String>,
+}
+
+async fn find_stored_metadata<C: ConnectionTrait>(
+    db: &C,
+    isrc: String,
+) -> Result<Option<StoredMetadata>, DbErr> {
+    let metadata = match isrc_metadata::Entity::find_by_id(isrc.clone())
+        .one(db)
+        .await?
+    {
+        Some(metadata) => metadata,
+        None => return Ok(None),
     };
-    Ok(Some(metadata))
-}
-
-async fn cover_art_archive_lookup(
-    client: &reqwest::Client,
-    mbids: &Vec<String>,
-) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
-    for id in mbids {
-        let request = client
-            .request(Method::GET, format!("{}{}", COVER_ART_ARCHIVE_BASE_URL, id))
-            .header(ACCEPT, "application/json")
-            .build()
-            .unwrap();
-
-        let response = client.execute(request).await?;
-        if response.status() != StatusCode::OK {
-            continue;
-        }
-        let caa_response: coverartarchive::Response = response.json().await?;
-        let image = caa_response.images.first().map(|img| img.image.clone());
-        return Ok(image);
+    let credits = isrc_metadata_artist_credits::Entity::find()
+        .filter(isrc_metadata_artist_credits::Column::Isrc.eq(isrc.clone()))
+        .order_by_asc(isrc_metadata_artist_credits::Column::Position)
+        .all(db)
+        .await?;
+    let genres = isrc_metadata_genres::Entity::find()
+        .filter(isrc_metadata_genres::Column::Isrc.eq(isrc))
+        .order_by_asc(isrc_metadata_genres::Column::Position)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|genre| genre.genre)
+        .collect();
+    Ok(Some(StoredMetadata {
+        metadata,
+        credits,
+        genres,
+    }))
+}
+
+// Everything that is part of Metadata, so that subscribers are not left with stale fields
+fn has_changed(previous: &StoredMetadata, current: &TrackMetadata) -> bool {
+    let metadata = &previous.metadata;
+    let credits_changed = previous.credits.len() != current.artist_credits.len()
+        || previous
+            .credits
+            .iter()
+            .zip(&current.artist_credits)
+            .any(|(previous, current)| {
+                previous.name != current.name
+                    || previous.join_phrase != current.join_phrase
+                    || previous.artist_mbid
+                        != parse_mbid(current.artist.as_ref().map(|artist| artist.mbid.clone()))
+            });
+    metadata.name != current.name
+        || metadata.artist != current.artist
+        || metadata.cover_art_url != current.cover_art_url
+        || metadata.release_title != current.release_title
+        || metadata.release_date != current.release_date
+        || metadata.length_ms != current.length_ms
+        || metadata.recording_mbid != parse_mbid(current.recording_mbid.clone())
+        || metadata.release_mbid != parse_mbid(current.release_mbid.clone())
+        || credits_changed
+        || previous.genres != current.genres
+}
+
+// Returns whether the cover art has been mirrored
+async fn mirror_cover_art(
+    db: &DatabaseConnection,
+    mirror: &CoverArtMirror,
+    isrc: String,
+    url: &str,
+) -> Result<bool, DbErr> {
+    if let Err(err) = mirror.mirror(&isrc, url).await {
+        warn!("Failed to mirror cover art for ISRC {}: {}", isrc, err);
+        return Ok(false);
     }
-    Ok(None)
+    isrc_metadata::Entity::update_many()
+        .col_expr(
+            isrc_metadata::Column::CoverArtMirroredAt,
+            Expr::value(Utc::now().with_timezone(&FixedOffset::east(0))),
+        )
+        .filter(isrc_metadata::Column::Isrc.eq(isrc))
+        .filter(isrc_metadata::Column::CoverArtUrl.eq(url))
+        .exec(db)
+        .await?;
+    Ok(true)
+}
+
+fn parse_mbid(mbid: Option<String>) -> Option<Uuid> {
+    mbid.and_then(|mbid| Uuid::parse_str(&mbid).ok())
 }
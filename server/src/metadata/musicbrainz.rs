@@ -30,6 +30,7 @@ pub struct Isrc {
 
 #[derive(Debug, Deserialize)]
 pub struct RecordingList {
+    #[serde(default)]
     pub recording: Vec<Recording>,
 }
 
@@ -38,33 +39,57 @@ pub struct RecordingList {
 pub struct Recording {
     pub id: String,
     pub title: String,
-    pub artist_credit: ArtistCredit,
-    pub release_list: ReleaseList,
+    pub length: Option<i32>,
+    pub artist_credit: Option<ArtistCredit>,
+    pub release_list: Option<ReleaseList>,
+    pub genre_list: Option<GenreList>,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ArtistCredit {
+    #[serde(default)]
     pub name_credit: Vec<NameCredit>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct NameCredit {
+    pub joinphrase: Option<String>,
+    // Name as credited on this recording, if it differs from the artist's name
+    pub name: Option<String>,
     pub artist: Artist,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Artist {
+    pub id: String,
     pub name: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ReleaseList {
+    #[serde(default)]
     pub release: Vec<Release>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Release {
     pub id: String,
     pub title: String,
+    // Official, Promotion, Bootleg or Pseudo-Release
+    pub status: Option<String>,
+    pub date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenreList {
+    #[serde(default)]
+    pub genre: Vec<Genre>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Genre {
+    pub name: String,
+    // Number of users who voted for the genre
+    pub count: Option<i32>,
 }
@@ -0,0 +1,317 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::env;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use itertools::Itertools;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use entity::sea_orm_active_enums::Service;
+
+use crate::metadata::provider::{
+    normalize_genre, ArtistCredit, CatalogTrack, MetadataProvider, ProviderResult,
+    ServiceIdProvider, TrackMetadata,
+};
+use crate::metadata::ratelimit::{LimitedClient, RateLimit, RateLimiter};
+
+pub const DEFAULT_APPLE_MUSIC_API_BASE_URL: &str = "https://api.music.apple.com/v1/";
+const ARTWORK_SIZE: &str = "1000";
+const SEARCH_LIMIT: usize = 5;
+const APPLE_MUSIC_ROOT_GENRE: &str = "Music";
+
+const APPLE_MUSIC_RATE_LIMIT: RateLimit = RateLimit {
+    capacity: 20,
+    per_second: 10.0,
+};
+
+pub struct AppleMusicCredentials {
+    pub team_id: String,
+    pub key_id: String,
+    // ES256 private key in PEM format
+    pub key_value: String,
+    pub storefront: String,
+}
+
+impl AppleMusicCredentials {
+    // None unless the team and key are set
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            team_id: env::var("APPLE_TEAM_ID").ok()?,
+            key_id: env::var("APPLE_MUSIC_KEY_ID").ok()?,
+            key_value: env::var("APPLE_MUSIC_KEY_VALUE").ok()?,
+            storefront: env::var("APPLE_MUSIC_STOREFRONT").unwrap_or("us".to_owned()),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DeveloperTokenPayload {
+    iss: String,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct SongsResponse {
+    data: Vec<Song>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    results: SearchResults,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResults {
+    // Missing if nothing matched
+    songs: Option<SongsResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Song {
+    // Catalog ID, the same across storefronts
+    id: String,
+    attributes: SongAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SongAttributes {
+    name: String,
+    // Already joined, e.g. "Artist A & Artist B"
+    artist_name: String,
+    album_name: Option<String>,
+    release_date: Option<String>,
+    duration_in_millis: Option<i32>,
+    isrc: Option<String>,
+    artwork: Option<Artwork>,
+    #[serde(default)]
+    genre_names: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Artwork {
+    // Template with {w} and {h} placeholders
+    url: String,
+}
+
+// Looks up catalog songs with a developer token signed by a MusicKit key
+pub struct AppleMusicProvider {
+    credentials: AppleMusicCredentials,
+    api_base_url: String,
+    client: LimitedClient,
+    token: Mutex<Option<(String, DateTime<Utc>)>>,
+}
+
+impl AppleMusicProvider {
+    pub fn new(
+        limiter: RateLimiter,
+        credentials: AppleMusicCredentials,
+        api_base_url: String,
+    ) -> Self {
+        Self {
+            credentials,
+            api_base_url,
+            client: LimitedClient::new(limiter, "applemusic", APPLE_MUSIC_RATE_LIMIT),
+            token: Mutex::new(None),
+        }
+    }
+
+    async fn developer_token(&self) -> ProviderResult<String> {
+        let mut token = self.token.lock().await;
+        if let Some((developer_token, expires_at)) = token.as_ref() {
+            if *expires_at > Utc::now() {
+                return Ok(developer_token.clone());
+            }
+        }
+        let issued_at = Utc::now();
+        let expires_at = issued_at + Duration::hours(12);
+        let developer_token = jsonwebtoken::encode(
+            &Header {
+                typ: None,
+                alg: Algorithm::ES256,
+                kid: Some(self.credentials.key_id.clone()),
+                ..Header::default()
+            },
+            &DeveloperTokenPayload {
+                iss: self.credentials.team_id.clone(),
+                iat: issued_at.timestamp(),
+                exp: expires_at.timestamp(),
+            },
+            &EncodingKey::from_ec_pem(self.credentials.key_value.as_ref())?,
+        )?;
+        *token = Some((developer_token.clone(), expires_at - Duration::minutes(1)));
+        Ok(developer_token)
+    }
+
+    async fn get_song(&self, id: &str) -> ProviderResult<Option<Song>> {
+        let developer_token = self.developer_token().await?;
+        let request = self
+            .client
+            .request(
+                Method::GET,
+                format!(
+                    "{}catalog/{}/songs/{}",
+                    self.api_base_url, self.credentials.storefront, id
+                ),
+            )
+            .bearer_auth(developer_token)
+            .build()?;
+        let response = self.client.execute(request).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response: SongsResponse = response.error_for_status()?.json().await?;
+        Ok(response.data.into_iter().next())
+    }
+
+    async fn search_by_isrc(&self, isrc: &str) -> ProviderResult<Option<Song>> {
+        let developer_token = self.developer_token().await?;
+        let request = self
+            .client
+            .request(
+                Method::GET,
+                format!(
+                    "{}catalog/{}/songs",
+                    self.api_base_url, self.credentials.storefront
+                ),
+            )
+            .query(&[("filter[isrc]", isrc)])
+            .bearer_auth(developer_token)
+            .build()?;
+        let response: SongsResponse = self
+            .client
+            .execute(request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.data.into_iter().next())
+    }
+
+    async fn search_by_term(&self, term: &str) -> ProviderResult<Vec<Song>> {
+        let developer_token = self.developer_token().await?;
+        let request = self
+            .client
+            .request(
+                Method::GET,
+                format!(
+                    "{}catalog/{}/search",
+                    self.api_base_url, self.credentials.storefront
+                ),
+            )
+            .query(&[
+                ("term", term),
+                ("types", "songs"),
+                ("limit", SEARCH_LIMIT.to_string().as_str()),
+            ])
+            .bearer_auth(developer_token)
+            .build()?;
+        let response: SearchResponse = self
+            .client
+            .execute(request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response
+            .results
+            .songs
+            .map(|songs| songs.data)
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for AppleMusicProvider {
+    fn name(&self) -> &'static str {
+        "Apple Music"
+    }
+
+    async fn lookup(
+        &self,
+        isrc: &str,
+        _pinned_recording_mbid: Option<&str>,
+    ) -> ProviderResult<Option<TrackMetadata>> {
+        let song = match self.search_by_isrc(isrc).await? {
+            Some(song) => song,
+            None => return Ok(None),
+        };
+        let attributes = song.attributes;
+        Ok(Some(TrackMetadata {
+            name: attributes.name,
+            artist: attributes.artist_name.clone(),
+            artist_credits: ArtistCredit::from_names(vec![attributes.artist_name]),
+            cover_art_url: attributes.artwork.map(|artwork| {
+                artwork
+                    .url
+                    .replace("{w}", ARTWORK_SIZE)
+                    .replace("{h}", ARTWORK_SIZE)
+            }),
+            release_title: attributes.album_name,
+            release_date: attributes.release_date,
+            length_ms: attributes.duration_in_millis,
+            recording_mbid: None,
+            release_mbid: None,
+            genres: attributes
+                .genre_names
+                .iter()
+                // Apple Music lists every song under "Music" as well
+                .filter(|genre| genre.as_str() != APPLE_MUSIC_ROOT_GENRE)
+                .map(|genre| normalize_genre(genre))
+                .unique()
+                .collect(),
+            match_reason: None,
+        }))
+    }
+}
+
+#[async_trait]
+impl ServiceIdProvider for AppleMusicProvider {
+    fn service(&self) -> Service {
+        Service::AppleMusic
+    }
+
+    async fn lookup_service_id(&self, isrc: &str) -> ProviderResult<Option<String>> {
+        Ok(self.search_by_isrc(isrc).await?.map(|song| song.id))
+    }
+
+    async fn lookup_isrc(&self, service_id: &str) -> ProviderResult<Option<String>> {
+        Ok(self
+            .get_song(service_id)
+            .await?
+            .and_then(|song| song.attributes.isrc))
+    }
+
+    async fn search_tracks(&self, name: &str, artist: &str) -> ProviderResult<Vec<CatalogTrack>> {
+        let songs = self.search_by_term(&format!("{} {}", name, artist)).await?;
+        Ok(songs
+            .into_iter()
+            .map(|song| CatalogTrack {
+                service_id: song.id,
+                name: song.attributes.name,
+                artist: song.attributes.artist_name,
+                length_ms: song.attributes.duration_in_millis,
+                isrc: song.attributes.isrc,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,308 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::{info, warn};
+
+use entity::sea_orm_active_enums::Service;
+
+use crate::metadata::ratelimit::RateLimiter;
+
+pub mod apple_music;
+pub mod musicbrainz;
+pub mod spotify;
+
+pub type ProviderResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+#[derive(Debug, Clone)]
+pub struct ArtistCredit {
+    pub name: String,
+    // Text joining this credit to the next one, e.g. " feat. "
+    pub join_phrase: String,
+    pub artist: Option<CreditedArtist>,
+}
+
+// The artist behind a credit, under its own name rather than the credited one
+#[derive(Debug, Clone)]
+pub struct CreditedArtist {
+    pub mbid: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackMetadata {
+    pub name: String,
+    // All credits joined into a single line, as displayed
+    pub artist: String,
+    pub artist_credits: Vec<ArtistCredit>,
+    pub cover_art_url: Option<String>,
+    pub release_title: Option<String>,
+    // YYYY, YYYY-MM or YYYY-MM-DD, depending on what the provider knows
+    pub release_date: Option<String>,
+    pub length_ms: Option<i32>,
+    pub recording_mbid: Option<String>,
+    pub release_mbid: Option<String>,
+    // Lowercase, most relevant first
+    pub genres: Vec<String>,
+    // Why the provider settled on this track, if it had several candidates to choose from
+    pub match_reason: Option<String>,
+}
+
+impl ArtistCredit {
+    // Credits for providers that only list artist names, joined like MusicBrainz would
+    pub fn from_names(names: Vec<String>) -> Vec<ArtistCredit> {
+        let count = names.len();
+        names
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| ArtistCredit {
+                name,
+                join_phrase: match count - index {
+                    1 => "",
+                    2 => " & ",
+                    _ => ", ",
+                }
+                .to_owned(),
+                artist: None,
+            })
+            .collect()
+    }
+
+    pub fn join(credits: &[ArtistCredit]) -> String {
+        credits
+            .iter()
+            .map(|credit| format!("{}{}", credit.name, credit.join_phrase))
+            .collect()
+    }
+}
+
+// Genres are matched case-insensitively, across providers
+pub fn normalize_genre(genre: &str) -> String {
+    genre.trim().to_lowercase()
+}
+
+#[async_trait]
+pub trait MetadataProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Ok(None) if the provider does not know the ISRC. A pinned MusicBrainz recording
+    // overrides the provider's own choice, where it has one
+    async fn lookup(
+        &self,
+        isrc: &str,
+        pinned_recording_mbid: Option<&str>,
+    ) -> ProviderResult<Option<TrackMetadata>>;
+}
+
+// A catalog track, as found by a search
+#[derive(Debug, Clone)]
+pub struct CatalogTrack {
+    pub service_id: String,
+    pub name: String,
+    pub artist: String,
+    pub length_ms: Option<i32>,
+    pub isrc: Option<String>,
+}
+
+// Catalogs that can resolve an ISRC to the service's own track ID
+#[async_trait]
+pub trait ServiceIdProvider: Send + Sync {
+    fn service(&self) -> Service;
+
+    // Ok(None) if the catalog has no track with the ISRC
+    async fn lookup_service_id(&self, isrc: &str) -> ProviderResult<Option<String>>;
+
+    // The reverse of lookup_service_id, Ok(None) if the catalog has no such track or it
+    // lacks an ISRC
+    async fn lookup_isrc(&self, service_id: &str) -> ProviderResult<Option<String>>;
+
+    // Free text search by title and artist, best hits first
+    async fn search_tracks(&self, name: &str, artist: &str) -> ProviderResult<Vec<CatalogTrack>>;
+}
+
+// Where providers reach their catalogs, pointed at the catalog stub to run offline
+#[derive(Debug, Clone)]
+pub struct CatalogUrls {
+    pub musicbrainz: String,
+    pub cover_art_archive: String,
+    pub spotify_token: String,
+    pub spotify_api: String,
+    pub apple_music_api: String,
+}
+
+impl Default for CatalogUrls {
+    fn default() -> Self {
+        Self {
+            musicbrainz: musicbrainz::DEFAULT_MUSICBRAINZ_BASE_URL.to_owned(),
+            cover_art_archive: musicbrainz::DEFAULT_COVER_ART_ARCHIVE_BASE_URL.to_owned(),
+            spotify_token: spotify::DEFAULT_SPOTIFY_TOKEN_URL.to_owned(),
+            spotify_api: spotify::DEFAULT_SPOTIFY_API_BASE_URL.to_owned(),
+            apple_music_api: apple_music::DEFAULT_APPLE_MUSIC_API_BASE_URL.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProviderKind {
+    MusicBrainz,
+    Spotify,
+    AppleMusic,
+}
+
+impl FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "musicbrainz" => Ok(ProviderKind::MusicBrainz),
+            "spotify" => Ok(ProviderKind::Spotify),
+            "apple_music" => Ok(ProviderKind::AppleMusic),
+            other => Err(format!("Unknown metadata provider: {}", other)),
+        }
+    }
+}
+
+impl ProviderKind {
+    pub fn create(self, limiter: RateLimiter, urls: &CatalogUrls) -> Box<dyn MetadataProvider> {
+        match self {
+            ProviderKind::MusicBrainz => Box::new(musicbrainz::MusicBrainzProvider::new(
+                limiter,
+                urls.musicbrainz.clone(),
+                urls.cover_art_archive.clone(),
+            )),
+            ProviderKind::Spotify => Box::new(
+                spotify_provider(limiter, urls)
+                    .expect("SPOTIFY_CLIENT_ID and SPOTIFY_CLIENT_SECRET must be set"),
+            ),
+            ProviderKind::AppleMusic => {
+                Box::new(apple_music_provider(limiter, urls).expect(
+                    "APPLE_TEAM_ID, APPLE_MUSIC_KEY_ID and APPLE_MUSIC_KEY_VALUE must be set",
+                ))
+            }
+        }
+    }
+
+    // None for providers without a catalog of their own or without credentials, so that
+    // deployments without them can skip the backfill
+    pub fn create_service_id_provider(
+        self,
+        limiter: RateLimiter,
+        urls: &CatalogUrls,
+    ) -> Option<Box<dyn ServiceIdProvider>> {
+        let provider: Option<Box<dyn ServiceIdProvider>> = match self {
+            ProviderKind::MusicBrainz => {
+                warn!("MusicBrainz has no service IDs, skipping it");
+                return None;
+            }
+            ProviderKind::Spotify => {
+                spotify_provider(limiter, urls).map(|provider| Box::new(provider) as _)
+            }
+            ProviderKind::AppleMusic => {
+                apple_music_provider(limiter, urls).map(|provider| Box::new(provider) as _)
+            }
+        };
+        if provider.is_none() {
+            warn!("No credentials for {:?}, skipping its service IDs", self);
+        }
+        provider
+    }
+}
+
+fn spotify_provider(limiter: RateLimiter, urls: &CatalogUrls) -> Option<spotify::SpotifyProvider> {
+    Some(spotify::SpotifyProvider::new(
+        limiter,
+        spotify::SpotifyCredentials::from_env()?,
+        urls.spotify_token.clone(),
+        urls.spotify_api.clone(),
+    ))
+}
+
+fn apple_music_provider(
+    limiter: RateLimiter,
+    urls: &CatalogUrls,
+) -> Option<apple_music::AppleMusicProvider> {
+    Some(apple_music::AppleMusicProvider::new(
+        limiter,
+        apple_music::AppleMusicCredentials::from_env()?,
+        urls.apple_music_api.clone(),
+    ))
+}
+
+// Providers in fallback order, the first one to know an ISRC wins
+#[derive(Clone)]
+pub struct MetadataProviders(Arc<Vec<Box<dyn MetadataProvider>>>);
+
+impl MetadataProviders {
+    pub fn new(providers: Vec<Box<dyn MetadataProvider>>) -> Self {
+        Self(Arc::new(providers))
+    }
+
+    // Errors are only returned if no provider found the ISRC and at least one failed,
+    // so that the job is retried instead of recording the ISRC as unknown
+    pub async fn lookup(
+        &self,
+        isrc: &str,
+        pinned_recording_mbid: Option<&str>,
+    ) -> ProviderResult<Option<TrackMetadata>> {
+        let mut last_error = None;
+        for provider in self.0.iter() {
+            match provider.lookup(isrc, pinned_recording_mbid).await {
+                Ok(Some(metadata)) => {
+                    info!("Found metadata for ISRC {} on {}", isrc, provider.name());
+                    return Ok(Some(metadata));
+                }
+                Ok(None) => info!("No metadata for ISRC {} on {}", isrc, provider.name()),
+                Err(err) => {
+                    warn!(
+                        "Lookup of ISRC {} on {} failed: {}",
+                        isrc,
+                        provider.name(),
+                        err
+                    );
+                    last_error = Some(err);
+                }
+            }
+        }
+        match last_error {
+            Some(err) => Err(err),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ServiceIdProviders(Arc<Vec<Box<dyn ServiceIdProvider>>>);
+
+impl ServiceIdProviders {
+    pub fn new(providers: Vec<Box<dyn ServiceIdProvider>>) -> Self {
+        Self(Arc::new(providers))
+    }
+
+    pub fn services(&self) -> Vec<Service> {
+        self.0.iter().map(|provider| provider.service()).collect()
+    }
+
+    pub fn get(&self, service: &Service) -> Option<&dyn ServiceIdProvider> {
+        self.0
+            .iter()
+            .find(|provider| provider.service() == *service)
+            .map(|provider| provider.as_ref())
+    }
+}
@@ -0,0 +1,457 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cmp::Reverse;
+
+use async_trait::async_trait;
+use axum::http::Method;
+use fred::bytes::Buf;
+use itertools::Itertools;
+use log::warn;
+use reqwest::header::{ACCEPT, USER_AGENT};
+use reqwest::StatusCode;
+use serde_xml_rs::from_reader;
+
+use crate::metadata::provider::{
+    normalize_genre, ArtistCredit, CreditedArtist, MetadataProvider, ProviderResult, TrackMetadata,
+};
+use crate::metadata::ratelimit::{LimitedClient, RateLimit, RateLimiter};
+use crate::metadata::{coverartarchive, musicbrainz};
+
+pub const DEFAULT_MUSICBRAINZ_BASE_URL: &str = "https://musicbrainz.org/ws/2/";
+pub const DEFAULT_COVER_ART_ARCHIVE_BASE_URL: &str = "https://coverartarchive.org/release/";
+// Sorts after any YYYY, YYYY-MM or YYYY-MM-DD
+const UNKNOWN_DATE: &str = "9999";
+
+// MusicBrainz allows one request per second and IP
+const MUSICBRAINZ_RATE_LIMIT: RateLimit = RateLimit {
+    capacity: 1,
+    per_second: 1.0,
+};
+const COVER_ART_ARCHIVE_RATE_LIMIT: RateLimit = RateLimit {
+    capacity: 5,
+    per_second: 5.0,
+};
+
+pub struct MusicBrainzProvider {
+    musicbrainz: LimitedClient,
+    musicbrainz_base_url: String,
+    cover_art_archive: LimitedClient,
+    cover_art_archive_base_url: String,
+}
+
+impl MusicBrainzProvider {
+    pub fn new(
+        limiter: RateLimiter,
+        musicbrainz_base_url: String,
+        cover_art_archive_base_url: String,
+    ) -> Self {
+        Self {
+            musicbrainz: LimitedClient::new(limiter.clone(), "musicbrainz", MUSICBRAINZ_RATE_LIMIT),
+            musicbrainz_base_url,
+            cover_art_archive: LimitedClient::new(
+                limiter,
+                "coverartarchive",
+                COVER_ART_ARCHIVE_RATE_LIMIT,
+            ),
+            cover_art_archive_base_url,
+        }
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for MusicBrainzProvider {
+    fn name(&self) -> &'static str {
+        "MusicBrainz"
+    }
+
+    async fn lookup(
+        &self,
+        isrc: &str,
+        pinned_recording_mbid: Option<&str>,
+    ) -> ProviderResult<Option<TrackMetadata>> {
+        // Fetch general metadata from MusicBrainz
+        let mb_metadata = match musicbrainz_isrc_lookup(
+            &self.musicbrainz,
+            &self.musicbrainz_base_url,
+            isrc,
+            pinned_recording_mbid,
+        )
+        .await?
+        {
+            Some(mb_metadata) => mb_metadata,
+            None => return Ok(None),
+        };
+
+        // Fetch cover art url from CoverArtArchive
+        let release_mbids = mb_metadata
+            .releases
+            .iter()
+            .map(|release| release.id.clone())
+            .collect();
+        let cover_url = cover_art_archive_lookup(
+            &self.cover_art_archive,
+            &self.cover_art_archive_base_url,
+            &release_mbids,
+        )
+        .await?;
+
+        let release = mb_metadata.releases.into_iter().next();
+        Ok(Some(TrackMetadata {
+            name: mb_metadata.name,
+            artist: ArtistCredit::join(&mb_metadata.artist_credits),
+            artist_credits: mb_metadata.artist_credits,
+            cover_art_url: cover_url,
+            release_title: release.as_ref().map(|release| release.title.clone()),
+            release_date: release.as_ref().and_then(|release| release.date.clone()),
+            length_ms: mb_metadata.length_ms,
+            recording_mbid: Some(mb_metadata.mbid),
+            release_mbid: release.map(|release| release.id),
+            genres: mb_metadata.genres,
+            match_reason: Some(mb_metadata.match_reason),
+        }))
+    }
+}
+
+struct MusicBrainzMetadata {
+    mbid: String,
+    name: String,
+    artist_credits: Vec<ArtistCredit>,
+    length_ms: Option<i32>,
+    releases: Vec<musicbrainz::Release>,
+    genres: Vec<String>,
+    match_reason: String,
+}
+
+async fn musicbrainz_isrc_lookup(
+    client: &LimitedClient,
+    base_url: &str,
+    isrc: &str,
+    pinned_recording_mbid: Option<&str>,
+) -> ProviderResult<Option<MusicBrainzMetadata>> {
+    let request = client
+        .request(Method::GET, format!("{}isrc/{}", base_url, isrc))
+        .query(&[("inc", "artists+releases+genres")])
+        .header(ACCEPT, "application/xml")
+        .header(USER_AGENT, "de.julianostarek.motif")
+        .build()
+        .unwrap();
+
+    let response = client.execute(request).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let metadata_response: musicbrainz::Metadata = from_reader(response.bytes().await?.reader())?;
+    let recordings = metadata_response.isrc.recording_list.recording;
+    let (recording, match_reason) = match choose_recording(recordings, pinned_recording_mbid) {
+        Some(choice) => choice,
+        None => return Ok(None),
+    };
+
+    let mut releases = recording
+        .release_list
+        .map(|list| list.release)
+        .unwrap_or_default();
+    // Official releases first, as they are the most likely to have cover art
+    releases.sort_by_key(|release| (!is_official(release), date_key(release.date.as_deref())));
+    let metadata = MusicBrainzMetadata {
+        mbid: recording.id,
+        name: recording.title,
+        artist_credits: recording
+            .artist_credit
+            .map(|credit| credit.name_credit)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|credit| ArtistCredit {
+                name: credit.name.unwrap_or_else(|| credit.artist.name.clone()),
+                join_phrase: credit.joinphrase.unwrap_or_default(),
+                artist: Some(CreditedArtist {
+                    mbid: credit.artist.id,
+                    name: credit.artist.name,
+                }),
+            })
+            .collect(),
+        length_ms: recording.length,
+        releases,
+        genres: recording
+            .genre_list
+            .iter()
+            .flat_map(|list| list.genre.iter())
+            .sorted_by_key(|genre| -genre.count.unwrap_or(0))
+            .map(|genre| normalize_genre(&genre.name))
+            .unique()
+            .collect(),
+        match_reason,
+    };
+    Ok(Some(metadata))
+}
+
+// An ISRC may be assigned to several recordings, e.g. for remasters listed separately.
+// Unless one is pinned, the best known one wins: most releases, then official ones,
+// then the earliest release. On a full tie, the first one listed wins
+fn choose_recording(
+    recordings: Vec<musicbrainz::Recording>,
+    pinned_recording_mbid: Option<&str>,
+) -> Option<(musicbrainz::Recording, String)> {
+    let count = recordings.len();
+    let mut pinned_missing = None;
+    if let Some(pinned) = pinned_recording_mbid {
+        match recordings
+            .iter()
+            .position(|recording| recording.id == pinned)
+        {
+            Some(index) => {
+                let recording = recordings.into_iter().nth(index)?;
+                return Some((recording, format!("Pinned recording, of {}", count)));
+            }
+            None => {
+                warn!("Pinned recording {} is not listed for the ISRC", pinned);
+                pinned_missing = Some(pinned.to_owned());
+            }
+        }
+    }
+
+    // max_by_key returns the last of equal elements
+    let recording = recordings.into_iter().rev().max_by_key(|recording| {
+        let releases: &[musicbrainz::Release] = recording
+            .release_list
+            .as_ref()
+            .map_or(&[], |list| list.release.as_slice());
+        (
+            releases.len(),
+            releases.iter().any(is_official),
+            Reverse(earliest_date(releases)),
+        )
+    })?;
+    let releases: &[musicbrainz::Release] = recording
+        .release_list
+        .as_ref()
+        .map_or(&[], |list| list.release.as_slice());
+    let mut reason = if count == 1 {
+        "Only recording".to_owned()
+    } else {
+        format!(
+            "Ranked first of {}: {} release{}, {}, earliest {}",
+            count,
+            releases.len(),
+            if releases.len() == 1 { "" } else { "s" },
+            if releases.iter().any(is_official) {
+                "official"
+            } else {
+                "none official"
+            },
+            releases
+                .iter()
+                .filter_map(|release| release.date.as_deref())
+                .min()
+                .unwrap_or("unknown"),
+        )
+    };
+    if let Some(pinned) = pinned_missing {
+        reason = format!("{} (pinned recording {} not listed)", reason, pinned);
+    }
+    Some((recording, reason))
+}
+
+fn is_official(release: &musicbrainz::Release) -> bool {
+    release.status.as_deref() == Some("Official")
+}
+
+// Partial dates compare correctly as strings, unknown dates sort last
+fn date_key(date: Option<&str>) -> String {
+    date.unwrap_or(UNKNOWN_DATE).to_owned()
+}
+
+fn earliest_date(releases: &[musicbrainz::Release]) -> String {
+    releases
+        .iter()
+        .map(|release| date_key(release.date.as_deref()))
+        .min()
+        .unwrap_or(UNKNOWN_DATE.to_owned())
+}
+
+async fn cover_art_archive_lookup(
+    client: &LimitedClient,
+    base_url: &str,
+    mbids: &Vec<String>,
+) -> ProviderResult<Option<String>> {
+    for id in mbids {
+        let request = client
+            .request(Method::GET, format!("{}{}", base_url, id))
+            .header(ACCEPT, "application/json")
+            .build()
+            .unwrap();
+
+        let response = client.execute(request).await?;
+        if response.status() != StatusCode::OK {
+            continue;
+        }
+        let caa_response: coverartarchive::Response = response.json().await?;
+        let image = caa_response.images.first().map(|img| img.image.clone());
+        return Ok(image);
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFICIAL: Option<&str> = Some("Official");
+
+    fn recording(id: &str, releases: &[(Option<&str>, Option<&str>)]) -> musicbrainz::Recording {
+        musicbrainz::Recording {
+            id: id.to_owned(),
+            title: "Song".to_owned(),
+            length: None,
+            artist_credit: None,
+            release_list: Some(musicbrainz::ReleaseList {
+                release: releases
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (status, date))| musicbrainz::Release {
+                        id: format!("{}-{}", id, index),
+                        title: "Album".to_owned(),
+                        status: status.map(str::to_owned),
+                        date: date.map(str::to_owned),
+                    })
+                    .collect(),
+            }),
+            genre_list: None,
+        }
+    }
+
+    fn chosen(
+        recordings: Vec<musicbrainz::Recording>,
+        pinned_recording_mbid: Option<&str>,
+    ) -> (String, String) {
+        let (recording, reason) = choose_recording(recordings, pinned_recording_mbid).unwrap();
+        (recording.id, reason)
+    }
+
+    #[test]
+    fn no_recordings() {
+        assert!(choose_recording(vec![], None).is_none());
+        assert!(choose_recording(vec![], Some("a")).is_none());
+    }
+
+    #[test]
+    fn only_recording() {
+        let recordings = vec![recording("a", &[])];
+        assert_eq!(
+            chosen(recordings, None),
+            ("a".to_owned(), "Only recording".to_owned())
+        );
+    }
+
+    #[test]
+    fn most_releases_first() {
+        let recordings = vec![
+            recording("a", &[(OFFICIAL, Some("2001"))]),
+            recording("b", &[(None, Some("2010")), (None, None)]),
+        ];
+        assert_eq!(
+            chosen(recordings, None),
+            (
+                "b".to_owned(),
+                "Ranked first of 2: 2 releases, none official, earliest 2010".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn official_breaks_tie_on_release_count() {
+        let recordings = vec![
+            recording("a", &[(Some("Bootleg"), Some("2001"))]),
+            recording("b", &[(OFFICIAL, Some("2010"))]),
+        ];
+        assert_eq!(chosen(recordings, None).0, "b");
+    }
+
+    #[test]
+    fn earliest_date_breaks_tie_on_status() {
+        let recordings = vec![
+            recording("a", &[(OFFICIAL, Some("2010-01-01"))]),
+            recording("b", &[(OFFICIAL, Some("2009-12"))]),
+            recording("c", &[(OFFICIAL, None)]),
+        ];
+        assert_eq!(
+            chosen(recordings, None),
+            (
+                "b".to_owned(),
+                "Ranked first of 3: 1 release, official, earliest 2009-12".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn first_listed_breaks_full_tie() {
+        let recordings = vec![
+            recording("a", &[(OFFICIAL, Some("2010"))]),
+            recording("b", &[(OFFICIAL, Some("2010"))]),
+        ];
+        assert_eq!(chosen(recordings, None).0, "a");
+    }
+
+    #[test]
+    fn pinned_recording_wins() {
+        let recordings = vec![
+            recording("a", &[(OFFICIAL, Some("2001")), (OFFICIAL, None)]),
+            recording("b", &[]),
+        ];
+        assert_eq!(
+            chosen(recordings, Some("b")),
+            ("b".to_owned(), "Pinned recording, of 2".to_owned())
+        );
+    }
+
+    #[test]
+    fn missing_pinned_recording_falls_back_to_ranking() {
+        let recordings = vec![recording("a", &[]), recording("b", &[(None, None)])];
+        assert_eq!(
+            chosen(recordings, Some("c")),
+            (
+                "b".to_owned(),
+                "Ranked first of 2: 1 release, none official, earliest unknown \
+                 (pinned recording c not listed)"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn ranks_recordings_of_fixture() {
+        let response: musicbrainz::Metadata = from_reader(
+            include_bytes!("../../../fixtures/catalog/musicbrainz/ws/2/isrc/ZZMTF2200005.xml")
+                .as_slice(),
+        )
+        .unwrap();
+        let recordings = response.isrc.recording_list.recording;
+        // Statuses carry an ID attribute in actual responses
+        assert_eq!(
+            recordings[1].release_list.as_ref().unwrap().release[0].status,
+            OFFICIAL.map(str::to_owned)
+        );
+        assert_eq!(
+            chosen(recordings, None),
+            (
+                "00000000-0000-4000-8000-000000000113".to_owned(),
+                "Ranked first of 3: 2 releases, official, earliest 2018-05-04".to_owned()
+            )
+        );
+    }
+}
@@ -0,0 +1,282 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::env;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use entity::sea_orm_active_enums::Service;
+
+use crate::metadata::provider::{
+    ArtistCredit, CatalogTrack, MetadataProvider, ProviderResult, ServiceIdProvider, TrackMetadata,
+};
+use crate::metadata::ratelimit::{LimitedClient, RateLimit, RateLimiter};
+
+pub const DEFAULT_SPOTIFY_TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+pub const DEFAULT_SPOTIFY_API_BASE_URL: &str = "https://api.spotify.com/v1/";
+const SEARCH_LIMIT: usize = 5;
+
+// Spotify does not publish its limit, which is computed over a rolling 30s window
+const SPOTIFY_RATE_LIMIT: RateLimit = RateLimit {
+    capacity: 10,
+    per_second: 5.0,
+};
+
+pub struct SpotifyCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl SpotifyCredentials {
+    // None unless both are set
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            client_id: env::var("SPOTIFY_CLIENT_ID").ok()?,
+            client_secret: env::var("SPOTIFY_CLIENT_SECRET").ok()?,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    tracks: Paging,
+}
+
+#[derive(Deserialize, Debug)]
+struct Paging {
+    items: Vec<Track>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Track {
+    id: String,
+    name: String,
+    duration_ms: i32,
+    artists: Vec<Artist>,
+    album: Album,
+    external_ids: Option<ExternalIds>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ExternalIds {
+    isrc: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Artist {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Album {
+    name: String,
+    // YYYY, YYYY-MM or YYYY-MM-DD, as per release_date_precision
+    release_date: Option<String>,
+    images: Vec<Image>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Image {
+    url: String,
+}
+
+// Looks up tracks with an app-only token from the client credentials flow
+pub struct SpotifyProvider {
+    credentials: SpotifyCredentials,
+    token_url: String,
+    api_base_url: String,
+    client: LimitedClient,
+    token: Mutex<Option<(String, DateTime<Utc>)>>,
+}
+
+impl SpotifyProvider {
+    pub fn new(
+        limiter: RateLimiter,
+        credentials: SpotifyCredentials,
+        token_url: String,
+        api_base_url: String,
+    ) -> Self {
+        Self {
+            credentials,
+            token_url,
+            api_base_url,
+            client: LimitedClient::new(limiter, "spotify", SPOTIFY_RATE_LIMIT),
+            token: Mutex::new(None),
+        }
+    }
+
+    async fn access_token(&self) -> ProviderResult<String> {
+        let mut token = self.token.lock().await;
+        if let Some((access_token, expires_at)) = token.as_ref() {
+            if *expires_at > Utc::now() {
+                return Ok(access_token.clone());
+            }
+        }
+        let request = self
+            .client
+            .request(Method::POST, &self.token_url)
+            .basic_auth(
+                &self.credentials.client_id,
+                Some(&self.credentials.client_secret),
+            )
+            .form(&[("grant_type", "client_credentials")])
+            .build()?;
+        let response: TokenResponse = self
+            .client
+            .execute(request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // Renew a minute early, so that the token does not expire mid-request
+        let expires_at = Utc::now() + Duration::seconds(response.expires_in - 60);
+        *token = Some((response.access_token.clone(), expires_at));
+        Ok(response.access_token)
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> ProviderResult<Vec<Track>> {
+        let access_token = self.access_token().await?;
+        let request = self
+            .client
+            .request(Method::GET, format!("{}search", self.api_base_url))
+            .query(&[
+                ("q", query),
+                ("type", "track"),
+                ("limit", limit.to_string().as_str()),
+            ])
+            .bearer_auth(access_token)
+            .build()?;
+        let response: SearchResponse = self
+            .client
+            .execute(request)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.tracks.items)
+    }
+
+    async fn get_track(&self, id: &str) -> ProviderResult<Option<Track>> {
+        let access_token = self.access_token().await?;
+        let request = self
+            .client
+            .request(Method::GET, format!("{}tracks/{}", self.api_base_url, id))
+            .bearer_auth(access_token)
+            .build()?;
+        let response = self.client.execute(request).await?;
+        // Malformed IDs are answered with 400
+        if response.status() == StatusCode::NOT_FOUND
+            || response.status() == StatusCode::BAD_REQUEST
+        {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    async fn search_by_isrc(&self, isrc: &str) -> ProviderResult<Option<Track>> {
+        let tracks = self.search(&format!("isrc:{}", isrc), 1).await?;
+        Ok(tracks.into_iter().next())
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for SpotifyProvider {
+    fn name(&self) -> &'static str {
+        "Spotify"
+    }
+
+    async fn lookup(
+        &self,
+        isrc: &str,
+        _pinned_recording_mbid: Option<&str>,
+    ) -> ProviderResult<Option<TrackMetadata>> {
+        let track = match self.search_by_isrc(isrc).await? {
+            Some(track) => track,
+            None => return Ok(None),
+        };
+        let artist_credits = ArtistCredit::from_names(
+            track
+                .artists
+                .into_iter()
+                .map(|artist| artist.name)
+                .collect(),
+        );
+        Ok(Some(TrackMetadata {
+            name: track.name,
+            artist: ArtistCredit::join(&artist_credits),
+            artist_credits,
+            cover_art_url: track.album.images.into_iter().next().map(|image| image.url),
+            release_title: Some(track.album.name),
+            release_date: track.album.release_date,
+            length_ms: Some(track.duration_ms),
+            recording_mbid: None,
+            release_mbid: None,
+            // Spotify only assigns genres to artists
+            genres: vec![],
+            match_reason: None,
+        }))
+    }
+}
+
+#[async_trait]
+impl ServiceIdProvider for SpotifyProvider {
+    fn service(&self) -> Service {
+        Service::Spotify
+    }
+
+    async fn lookup_service_id(&self, isrc: &str) -> ProviderResult<Option<String>> {
+        Ok(self.search_by_isrc(isrc).await?.map(|track| track.id))
+    }
+
+    async fn lookup_isrc(&self, service_id: &str) -> ProviderResult<Option<String>> {
+        Ok(self
+            .get_track(service_id)
+            .await?
+            .and_then(|track| track.external_ids)
+            .and_then(|ids| ids.isrc))
+    }
+
+    async fn search_tracks(&self, name: &str, artist: &str) -> ProviderResult<Vec<CatalogTrack>> {
+        let query = format!("track:{} artist:{}", name, artist);
+        let tracks = self.search(&query, SEARCH_LIMIT).await?;
+        Ok(tracks
+            .into_iter()
+            .map(|track| CatalogTrack {
+                service_id: track.id,
+                name: track.name,
+                artist: ArtistCredit::join(&ArtistCredit::from_names(
+                    track
+                        .artists
+                        .into_iter()
+                        .map(|artist| artist.name)
+                        .collect(),
+                )),
+                length_ms: Some(track.duration_ms),
+                isrc: track.external_ids.and_then(|ids| ids.isrc),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,308 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use fred::clients::RedisClient;
+use fred::interfaces::LuaInterface;
+use log::warn;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{IntoUrl, Method, Request, RequestBuilder, Response, StatusCode};
+use tokio::time::sleep;
+
+const KEY_PREFIX: &str = "ratelimit:";
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+const MAX_RETRIES: u32 = 3;
+
+// Refills the bucket by elapsed server time and takes a token if possible,
+// returns the milliseconds to wait before trying again
+const ACQUIRE_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local capacity = tonumber(ARGV[1])
+local per_second = tonumber(ARGV[2])
+local paused_until = tonumber(redis.call('HGET', KEYS[1], 'paused_until') or '0')
+if paused_until > now then
+    return paused_until - now
+end
+local tokens = tonumber(redis.call('HGET', KEYS[1], 'tokens') or ARGV[1])
+local updated = tonumber(redis.call('HGET', KEYS[1], 'updated') or now)
+tokens = math.min(capacity, tokens + (now - updated) * per_second / 1000)
+local wait = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    wait = math.ceil((1 - tokens) * 1000 / per_second)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', now)
+redis.call('PEXPIRE', KEYS[1], 60000)
+return wait
+"#;
+
+const PAUSE_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local paused_until = now + tonumber(ARGV[1])
+local current = tonumber(redis.call('HGET', KEYS[1], 'paused_until') or '0')
+if paused_until > current then
+    redis.call('HSET', KEYS[1], 'paused_until', paused_until)
+end
+redis.call('PEXPIRE', KEYS[1], math.max(60000, tonumber(ARGV[1])))
+return 0
+"#;
+
+#[derive(Debug, Copy, Clone)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub per_second: f64,
+}
+
+struct MemoryBucket {
+    tokens: f64,
+    updated: Instant,
+    paused_until: Option<Instant>,
+}
+
+// Token buckets shared by all workers through Redis. Falls back to buckets local
+// to this process while Redis is unavailable, which only limits per process.
+#[derive(Clone)]
+pub struct RateLimiter {
+    redis: Option<Arc<RedisClient>>,
+    memory: Arc<Mutex<HashMap<String, MemoryBucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(redis: Option<Arc<RedisClient>>) -> Self {
+        Self {
+            redis,
+            memory: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn acquire(&self, key: &str, limit: RateLimit) {
+        loop {
+            let wait = self.try_acquire(key, limit).await;
+            if wait.is_zero() {
+                return;
+            }
+            sleep(wait).await;
+        }
+    }
+
+    // Blocks the bucket for everyone, e.g. when the provider asked to back off
+    pub async fn pause(&self, key: &str, duration: Duration) {
+        if let Some(redis) = &self.redis {
+            let result = redis
+                .eval::<i64, _, _, _>(
+                    PAUSE_SCRIPT,
+                    vec![format!("{}{}", KEY_PREFIX, key)],
+                    vec![duration.as_millis() as i64],
+                )
+                .await;
+            match result {
+                Ok(_) => return,
+                Err(err) => warn!("Rate limiter: Redis unavailable, pausing locally: {}", err),
+            }
+        }
+        let now = Instant::now();
+        let mut memory = self.memory.lock().unwrap();
+        let bucket = memory.entry(key.to_owned()).or_insert(MemoryBucket {
+            tokens: 0.0,
+            updated: now,
+            paused_until: None,
+        });
+        let paused_until = now + duration;
+        if bucket
+            .paused_until
+            .map_or(true, |current| paused_until > current)
+        {
+            bucket.paused_until = Some(paused_until);
+        }
+    }
+
+    async fn try_acquire(&self, key: &str, limit: RateLimit) -> Duration {
+        if let Some(redis) = &self.redis {
+            let result = redis
+                .eval::<i64, _, _, _>(
+                    ACQUIRE_SCRIPT,
+                    vec![format!("{}{}", KEY_PREFIX, key)],
+                    vec![limit.capacity.to_string(), limit.per_second.to_string()],
+                )
+                .await;
+            match result {
+                Ok(wait) => return Duration::from_millis(wait.max(0) as u64),
+                Err(err) => warn!("Rate limiter: Redis unavailable, limiting locally: {}", err),
+            }
+        }
+        self.try_acquire_memory(key, limit)
+    }
+
+    fn try_acquire_memory(&self, key: &str, limit: RateLimit) -> Duration {
+        let now = Instant::now();
+        let mut memory = self.memory.lock().unwrap();
+        let bucket = memory.entry(key.to_owned()).or_insert(MemoryBucket {
+            tokens: limit.capacity as f64,
+            updated: now,
+            paused_until: None,
+        });
+        if let Some(paused_until) = bucket.paused_until {
+            if paused_until > now {
+                return paused_until - now;
+            }
+            bucket.paused_until = None;
+        }
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_second).min(limit.capacity as f64);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / limit.per_second)
+        }
+    }
+}
+
+// HTTP client whose requests all draw from the same bucket
+#[derive(Clone)]
+pub struct LimitedClient {
+    client: reqwest::Client,
+    limiter: RateLimiter,
+    key: &'static str,
+    limit: RateLimit,
+}
+
+impl LimitedClient {
+    pub fn new(limiter: RateLimiter, key: &'static str, limit: RateLimit) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            limiter,
+            key,
+            limit,
+        }
+    }
+
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    // Waits for a token before every attempt, and pauses the bucket as long as
+    // the provider asks to on 429 or 503 responses before retrying
+    pub async fn execute(&self, request: Request) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            self.limiter.acquire(self.key, self.limit).await;
+            // Metadata requests never have streaming bodies, so they can always be cloned
+            let response = self.client.execute(request.try_clone().unwrap()).await?;
+            let status = response.status();
+            let throttled = status == StatusCode::SERVICE_UNAVAILABLE
+                || status == StatusCode::TOO_MANY_REQUESTS;
+            if !throttled || attempt == MAX_RETRIES {
+                return Ok(response);
+            }
+
+            let delay = retry_after(&response).unwrap_or(DEFAULT_RETRY_AFTER);
+            warn!(
+                "Rate limiter: {} responded {}, pausing for {:?}",
+                self.key, status, delay
+            );
+            self.limiter.pause(self.key, delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    parse_retry_after(response.headers().get(RETRY_AFTER)?.to_str().ok()?)
+}
+
+// Either delay seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        capacity: 2,
+        per_second: 1.0,
+    };
+
+    #[test]
+    fn takes_tokens_up_to_capacity() {
+        let limiter = RateLimiter::new(None);
+        assert!(limiter.try_acquire_memory("test", LIMIT).is_zero());
+        assert!(limiter.try_acquire_memory("test", LIMIT).is_zero());
+        let wait = limiter.try_acquire_memory("test", LIMIT);
+        assert!(wait > Duration::ZERO && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limit = RateLimit {
+            capacity: 1,
+            per_second: 1000.0,
+        };
+        let limiter = RateLimiter::new(None);
+        assert!(limiter.try_acquire_memory("test", limit).is_zero());
+        assert!(!limiter.try_acquire_memory("test", limit).is_zero());
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(limiter.try_acquire_memory("test", limit).is_zero());
+    }
+
+    #[test]
+    fn keeps_buckets_apart() {
+        let limit = RateLimit {
+            capacity: 1,
+            per_second: 1.0,
+        };
+        let limiter = RateLimiter::new(None);
+        assert!(limiter.try_acquire_memory("first", limit).is_zero());
+        assert!(limiter.try_acquire_memory("second", limit).is_zero());
+        assert!(!limiter.try_acquire_memory("first", limit).is_zero());
+    }
+
+    #[tokio::test]
+    async fn pause_blocks_the_bucket() {
+        let limiter = RateLimiter::new(None);
+        limiter.pause("test", Duration::from_secs(10)).await;
+        let wait = limiter.try_acquire_memory("test", LIMIT);
+        assert!(wait > Duration::from_secs(9) && wait <= Duration::from_secs(10));
+        // A shorter pause does not cut the current one short
+        limiter.pause("test", Duration::from_secs(1)).await;
+        assert!(limiter.try_acquire_memory("test", LIMIT) > Duration::from_secs(9));
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 5 "), Some(Duration::from_secs(5)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_retry_after("soon"), None);
+        let date = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let delay = parse_retry_after(&date).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,387 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeSet, HashSet};
+
+use apalis::prelude::{Job, JobContext, JobError, JobResult, Storage};
+use apalis::redis::RedisStorage;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use itertools::Itertools;
+use log::{error, info, warn};
+use sea_orm::sea_query::{OnConflict, Query};
+use sea_orm::ActiveValue::Set;
+use sea_orm::IdenStatic;
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr, DeriveColumn, EntityTrait,
+    EnumIter, NotSet, QueryFilter, QuerySelect, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use entity::sea_orm_active_enums::{MatchState, Service};
+use entity::{isrc_metadata, isrc_service_lookups, isrc_service_matches, isrc_services, motifs};
+
+use crate::domain::common::typedef::Isrc;
+use crate::domain::link::typedef::ServiceLink;
+use crate::domain::motif::pubsub::topic_service_ids_updated;
+use crate::metadata::matching::{best_match, TrackMatch, FLAG_CONFIDENCE};
+use crate::metadata::provider::ServiceIdProviders;
+use crate::pubsub::event::{Event, EventPayload};
+use crate::PubSubHandle;
+
+// Catalogs grow, so a search that found nothing is repeated after this many days
+const RELOOKUP_AFTER_DAYS: i64 = 7;
+const BACKFILL_BATCH_SIZE: u64 = 50;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackfillServiceIds {
+    pub isrc: Isrc,
+    // The link the ISRC was resolved from, stored as is instead of being looked up again
+    #[serde(default)]
+    pub linked: Option<ServiceLink>,
+}
+
+impl Job for BackfillServiceIds {
+    const NAME: &'static str = "motif::BackfillServiceIds";
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct ScheduleBackfillServiceIds {}
+
+impl Job for ScheduleBackfillServiceIds {
+    const NAME: &'static str = "motif::ScheduleBackfillServiceIds";
+}
+
+pub async fn schedule_backfill_service_ids(
+    _schedule: ScheduleBackfillServiceIds,
+    ctx: JobContext,
+) -> Result<JobResult, JobError> {
+    info!("Scheduling service ID backfill for ISRCs missing a service");
+
+    let db: &DatabaseConnection = ctx.data_opt().unwrap();
+    let providers: &ServiceIdProviders = ctx.data_opt().unwrap();
+    let mut storage: RedisStorage<BackfillServiceIds> = ctx
+        .data_opt::<RedisStorage<BackfillServiceIds>>()
+        .unwrap()
+        .clone();
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+    enum QueryAs {
+        Isrc,
+    }
+
+    let services = providers.services();
+    let now = Utc::now().with_timezone(&FixedOffset::east(0));
+    let relookup_before = now - Duration::days(RELOOKUP_AFTER_DAYS);
+    let isrcs_to_backfill = db
+        .transaction::<_, BTreeSet<String>, DbErr>(|txn| {
+            Box::pin(async move {
+                let mut isrcs_to_backfill = BTreeSet::new();
+                for service in services {
+                    let isrcs: Vec<String> = motifs::Entity::find()
+                        .select_only()
+                        .distinct()
+                        .column_as(motifs::Column::Isrc, QueryAs::Isrc)
+                        .filter(
+                            motifs::Column::Isrc.not_in_subquery(
+                                Query::select()
+                                    .column(isrc_services::Column::Isrc)
+                                    .from(isrc_services::Entity)
+                                    .cond_where(isrc_services::Column::Service.eq(service.clone()))
+                                    .to_owned(),
+                            ),
+                        )
+                        .filter(
+                            motifs::Column::Isrc.not_in_subquery(
+                                Query::select()
+                                    .column(isrc_service_lookups::Column::Isrc)
+                                    .from(isrc_service_lookups::Entity)
+                                    .cond_where(
+                                        Condition::all()
+                                            .add(
+                                                isrc_service_lookups::Column::Service
+                                                    .eq(service.clone()),
+                                            )
+                                            .add(
+                                                isrc_service_lookups::Column::LookedUpAt
+                                                    .gt(relookup_before),
+                                            ),
+                                    )
+                                    .to_owned(),
+                            ),
+                        )
+                        .limit(BACKFILL_BATCH_SIZE)
+                        .into_values::<_, QueryAs>()
+                        .all(txn)
+                        .await?;
+
+                    // Recorded up front, so that the next run does not schedule them again
+                    for isrc in &isrcs {
+                        record_lookup(txn, isrc.clone(), service.clone(), now).await?;
+                    }
+                    isrcs_to_backfill.extend(isrcs);
+                }
+                Ok(isrcs_to_backfill)
+            })
+        })
+        .await
+        .map_err(|err| JobError::Failed(Box::new(err)))?;
+
+    let mut scheduled = Vec::with_capacity(isrcs_to_backfill.len());
+    for isrc in isrcs_to_backfill {
+        match isrc.parse::<Isrc>() {
+            Ok(isrc) => {
+                storage
+                    .push(BackfillServiceIds {
+                        isrc: isrc.clone(),
+                        linked: None,
+                    })
+                    .await?;
+                scheduled.push(isrc);
+            }
+            Err(err) => warn!("Not backfilling service IDs for ISRC {}: {}", isrc, err),
+        }
+    }
+
+    if !scheduled.is_empty() {
+        info!(
+            "Scheduled service ID backfill for ISRCs: {}",
+            scheduled.iter().join(", ")
+        );
+    } else {
+        info!("Nothing to backfill: All ISRCs have service IDs or were looked up recently");
+    }
+
+    Ok(JobResult::Success)
+}
+
+pub async fn backfill_service_ids(
+    job: BackfillServiceIds,
+    ctx: JobContext,
+) -> Result<JobResult, JobError> {
+    info!("Backfilling service IDs for ISRC: {}", &job.isrc);
+    let isrc = job.isrc.to_string();
+
+    let db: &DatabaseConnection = ctx.data_opt().unwrap();
+    let providers: &ServiceIdProviders = ctx.data_opt().unwrap();
+
+    let existing: Vec<Service> = isrc_services::Entity::find()
+        .filter(isrc_services::Column::Isrc.eq(isrc.clone()))
+        .all(db)
+        .await
+        .map_err(|err| JobError::Failed(Box::new(err)))?
+        .into_iter()
+        .map(|model| model.service)
+        .collect();
+
+    let linked: Option<(Service, String)> = job
+        .linked
+        .clone()
+        .map(|link| (link.service.into(), link.id));
+
+    // Only loaded when a catalog does not know the ISRC
+    let mut stored_metadata: Option<Option<isrc_metadata::Model>> = None;
+    let mut found = Vec::new();
+    let mut matches = Vec::new();
+    for service in providers.services() {
+        if existing.contains(&service) {
+            continue;
+        }
+        if let Some((_, service_id)) = linked.as_ref().filter(|(linked, _)| *linked == service) {
+            found.push((service, service_id.clone()));
+            continue;
+        }
+        let provider = providers.get(&service).unwrap();
+        // Failed lookups are retried by the retry layer, misses after RELOOKUP_AFTER_DAYS
+        match provider.lookup_service_id(isrc.as_str()).await {
+            Ok(Some(service_id)) => {
+                found.push((service, service_id));
+                continue;
+            }
+            Ok(None) => info!("No {:?} ID for ISRC {}", service, &isrc),
+            Err(err) => {
+                error!("{:?} ID lookup for ISRC {} failed: {}", service, &isrc, err);
+                return Err(JobError::Failed(err));
+            }
+        }
+
+        // Regional reissues and re-releases often carry a different ISRC than the one
+        // MusicBrainz knows, so fall back to searching the catalog by title and artist
+        if stored_metadata.is_none() {
+            stored_metadata = Some(
+                isrc_metadata::Entity::find_by_id(isrc.clone())
+                    .one(db)
+                    .await
+                    .map_err(|err| JobError::Failed(Box::new(err)))?,
+            );
+        }
+        let metadata = match stored_metadata.as_ref().unwrap() {
+            Some(metadata) => metadata,
+            None => continue,
+        };
+        let candidates = match provider
+            .search_tracks(metadata.name.as_str(), metadata.artist.as_str())
+            .await
+        {
+            Ok(candidates) => candidates,
+            Err(err) => {
+                error!("{:?} search for ISRC {} failed: {}", service, &isrc, err);
+                return Err(JobError::Failed(err));
+            }
+        };
+        let rejected: HashSet<String> = isrc_service_matches::Entity::find()
+            .filter(isrc_service_matches::Column::Isrc.eq(isrc.clone()))
+            .filter(isrc_service_matches::Column::Service.eq(service.clone()))
+            .filter(isrc_service_matches::Column::State.eq(MatchState::Rejected))
+            .all(db)
+            .await
+            .map_err(|err| JobError::Failed(Box::new(err)))?
+            .into_iter()
+            .map(|model| model.service_id)
+            .collect();
+        match best_match(metadata, candidates, &rejected) {
+            Some(track_match) if track_match.is_accepted() => {
+                info!(
+                    "Matched {:?} ID {} for ISRC {} with confidence {:.2}",
+                    service, &track_match.track.service_id, &isrc, track_match.confidence
+                );
+                found.push((service.clone(), track_match.track.service_id.clone()));
+                matches.push((service, track_match, MatchState::Accepted));
+            }
+            Some(track_match) if track_match.confidence >= FLAG_CONFIDENCE => {
+                info!(
+                    "Flagged {:?} ID {} for ISRC {} with confidence {:.2}",
+                    service, &track_match.track.service_id, &isrc, track_match.confidence
+                );
+                matches.push((service, track_match, MatchState::Flagged));
+            }
+            _ => info!("No {:?} track matches ISRC {}", service, &isrc),
+        }
+    }
+    if found.is_empty() && matches.is_empty() {
+        return Ok(JobResult::Success);
+    }
+
+    let txn_isrc = isrc.clone();
+    let updated = !found.is_empty();
+    db.transaction::<_, (), DbErr>(|txn| {
+        Box::pin(async move {
+            for (service, service_id) in found {
+                isrc_services::Entity::insert(isrc_services::ActiveModel {
+                    id: NotSet,
+                    isrc: Set(txn_isrc.clone()),
+                    service: Set(service.clone()),
+                    service_id: Set(service_id),
+                })
+                .on_conflict(
+                    OnConflict::columns([
+                        isrc_services::Column::Isrc,
+                        isrc_services::Column::Service,
+                    ])
+                    .do_nothing()
+                    .to_owned(),
+                )
+                .exec(txn)
+                .await?;
+                isrc_service_lookups::Entity::delete_many()
+                    .filter(isrc_service_lookups::Column::Isrc.eq(txn_isrc.clone()))
+                    .filter(isrc_service_lookups::Column::Service.eq(service))
+                    .exec(txn)
+                    .await?;
+            }
+            for (service, track_match, state) in matches {
+                record_match(txn, txn_isrc.clone(), service, track_match, state).await?;
+            }
+            Ok(())
+        })
+    })
+    .await
+    .map_err(|err| JobError::Failed(Box::new(err)))?;
+    if !updated {
+        info!("Flagged service ID matches for review for ISRC: {}", &isrc);
+        return Ok(JobResult::Success);
+    }
+    info!("Successfully backfilled service IDs for ISRC: {}", &isrc);
+
+    if let Some(pubsub) = ctx.data_opt::<PubSubHandle<Event>>() {
+        pubsub
+            .publish(
+                topic_service_ids_updated(job.isrc.as_str()),
+                Event::system(EventPayload::ServiceIdsUpdated { isrc }),
+            )
+            .await;
+    }
+    Ok(JobResult::Success)
+}
+
+async fn record_lookup<C: ConnectionTrait>(
+    db: &C,
+    isrc: String,
+    service: Service,
+    looked_up_at: DateTime<FixedOffset>,
+) -> Result<(), DbErr> {
+    isrc_service_lookups::Entity::insert(isrc_service_lookups::ActiveModel {
+        id: NotSet,
+        isrc: Set(isrc),
+        service: Set(service),
+        looked_up_at: Set(looked_up_at),
+    })
+    .on_conflict(
+        OnConflict::columns([
+            isrc_service_lookups::Column::Isrc,
+            isrc_service_lookups::Column::Service,
+        ])
+        .update_column(isrc_service_lookups::Column::LookedUpAt)
+        .to_owned(),
+    )
+    .exec(db)
+    .await?;
+    Ok(())
+}
+
+// Keeps the state of a match that was seen before, so that reviewed matches stay reviewed
+async fn record_match<C: ConnectionTrait>(
+    db: &C,
+    isrc: String,
+    service: Service,
+    track_match: TrackMatch,
+    state: MatchState,
+) -> Result<(), DbErr> {
+    isrc_service_matches::Entity::insert(isrc_service_matches::ActiveModel {
+        id: NotSet,
+        isrc: Set(isrc),
+        service: Set(service),
+        service_id: Set(track_match.track.service_id),
+        track_isrc: Set(track_match.track.isrc),
+        name: Set(track_match.track.name),
+        artist: Set(track_match.track.artist),
+        length_ms: Set(track_match.track.length_ms),
+        confidence: Set(track_match.confidence),
+        state: Set(state),
+        created_at: NotSet,
+    })
+    .on_conflict(
+        OnConflict::columns([
+            isrc_service_matches::Column::Isrc,
+            isrc_service_matches::Column::Service,
+            isrc_service_matches::Column::ServiceId,
+        ])
+        .do_nothing()
+        .to_owned(),
+    )
+    .exec(db)
+    .await?;
+    Ok(())
+}
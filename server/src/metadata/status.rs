@@ -0,0 +1,177 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use chrono::{Duration, FixedOffset, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, IntoActiveModel,
+    QueryFilter,
+};
+
+use entity::isrc_metadata_status;
+use entity::sea_orm_active_enums::MetadataState;
+
+const RETRY_BASE_DELAY_SECS: i64 = 60;
+const RETRY_MAX_DELAY_SECS: i64 = 24 * 60 * 60;
+// Recent releases take a while to show up in the catalogs
+const NOT_FOUND_BASE_DELAY_DAYS: i64 = 1;
+const NOT_FOUND_MAX_DELAY_DAYS: i64 = 30;
+
+#[derive(Debug, Clone)]
+pub enum FetchOutcome {
+    Fetched,
+    NotFound,
+    Failed(String),
+}
+
+// Doubles with every failed attempt, starting at one minute and capped at a day
+pub fn retry_delay(attempts: i32) -> Duration {
+    let exponent = (attempts.max(1) - 1).min(20) as u32;
+    Duration::seconds((RETRY_BASE_DELAY_SECS << exponent).min(RETRY_MAX_DELAY_SECS))
+}
+
+// Doubles with every attempt that found nothing, starting at a day and capped at a month
+pub fn not_found_retry_delay(attempts: i32) -> Duration {
+    let exponent = (attempts.max(1) - 1).min(20) as u32;
+    Duration::days((NOT_FOUND_BASE_DELAY_DAYS << exponent).min(NOT_FOUND_MAX_DELAY_DAYS))
+}
+
+pub async fn mark_pending<C: ConnectionTrait>(db: &C, isrc: String) -> Result<(), DbErr> {
+    let now = Utc::now().with_timezone(&FixedOffset::east(0));
+    match isrc_metadata_status::Entity::find_by_id(isrc.clone())
+        .one(db)
+        .await?
+    {
+        Some(status) => {
+            let mut status = status.into_active_model();
+            status.state = Set(MetadataState::Pending);
+            status.next_retry_at = Set(None);
+            status.updated_at = Set(now);
+            status.update(db).await?;
+        }
+        None => {
+            isrc_metadata_status::ActiveModel {
+                isrc: Set(isrc),
+                updated_at: Set(now),
+                state: Set(MetadataState::Pending),
+                attempts: Set(0),
+                last_error: Set(None),
+                next_retry_at: Set(None),
+                manual: Set(false),
+                match_reason: Set(None),
+                pinned_recording_mbid: Set(None),
+            }
+            .insert(db)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn record_outcome<C: ConnectionTrait>(
+    db: &C,
+    isrc: String,
+    outcome: FetchOutcome,
+) -> Result<(), DbErr> {
+    let status = isrc_metadata_status::Entity::find_by_id(isrc.clone())
+        .one(db)
+        .await?
+        .ok_or(DbErr::RecordNotFound(format!(
+            "No metadata status for ISRC {}",
+            isrc
+        )))?;
+    let attempts = status.attempts + 1;
+    let now = Utc::now().with_timezone(&FixedOffset::east(0));
+
+    let mut status = status.into_active_model();
+    status.attempts = Set(attempts);
+    status.updated_at = Set(now);
+    match outcome {
+        FetchOutcome::Fetched => {
+            // A later failure backs off from the base delay again
+            status.attempts = Set(0);
+            status.state = Set(MetadataState::Fetched);
+            status.last_error = Set(None);
+            status.next_retry_at = Set(None);
+        }
+        FetchOutcome::NotFound => {
+            status.state = Set(MetadataState::NotFound);
+            status.last_error = Set(None);
+            status.next_retry_at = Set(Some(now + not_found_retry_delay(attempts)));
+        }
+        FetchOutcome::Failed(error) => {
+            status.state = Set(MetadataState::Failed);
+            status.last_error = Set(Some(error));
+            status.next_retry_at = Set(Some(now + retry_delay(attempts)));
+        }
+    }
+    status.update(db).await?;
+    Ok(())
+}
+
+pub async fn record_match_reason<C: ConnectionTrait>(
+    db: &C,
+    isrc: String,
+    match_reason: Option<String>,
+) -> Result<(), DbErr> {
+    isrc_metadata_status::Entity::update_many()
+        .col_expr(
+            isrc_metadata_status::Column::MatchReason,
+            Expr::value(match_reason),
+        )
+        .filter(isrc_metadata_status::Column::Isrc.eq(isrc))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_starts_at_base() {
+        assert_eq!(retry_delay(0), Duration::seconds(RETRY_BASE_DELAY_SECS));
+        assert_eq!(retry_delay(1), Duration::seconds(RETRY_BASE_DELAY_SECS));
+    }
+
+    #[test]
+    fn retry_delay_doubles() {
+        assert_eq!(retry_delay(2), Duration::seconds(2 * RETRY_BASE_DELAY_SECS));
+        assert_eq!(
+            retry_delay(5),
+            Duration::seconds(16 * RETRY_BASE_DELAY_SECS)
+        );
+    }
+
+    #[test]
+    fn not_found_retry_delay_starts_at_a_day() {
+        assert_eq!(not_found_retry_delay(1), Duration::days(1));
+        assert_eq!(not_found_retry_delay(3), Duration::days(4));
+        assert_eq!(not_found_retry_delay(i32::MAX), Duration::days(30));
+        assert!(not_found_retry_delay(1) >= retry_delay(i32::MAX));
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        assert_eq!(retry_delay(12), Duration::seconds(RETRY_MAX_DELAY_SECS));
+        assert_eq!(
+            retry_delay(i32::MAX),
+            Duration::seconds(RETRY_MAX_DELAY_SECS)
+        );
+    }
+}
@@ -0,0 +1,141 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::error::Error;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Extension, Router, Server};
+use log::{error, info, warn};
+use tokio::fs;
+
+use crate::metadata::provider::CatalogUrls;
+
+// Tried in order for request paths without a file extension
+const FIXTURE_EXTENSIONS: [&str; 2] = ["xml", "json"];
+// Replaced with the URL the stub is bound to in XML and JSON fixtures, which link back to the
+// stub for e.g. cover art images
+const STUB_URL_PLACEHOLDER: &str = "{{STUB_URL}}";
+
+struct Fixtures {
+    root: PathBuf,
+    stub_url: String,
+}
+
+// Serves recorded catalog responses from a directory, so that the metadata pipeline runs
+// without internet access. GET /musicbrainz/ws/2/isrc/USSM17800433 is answered with
+// musicbrainz/ws/2/isrc/USSM17800433.xml, or .json, whichever exists. Query strings and request
+// bodies are ignored, POST is answered like GET to stand in for token endpoints
+pub fn catalog_stub_router(root: PathBuf, stub_url: String) -> Router {
+    Router::new()
+        .route("/*path", get(fixture).post(fixture))
+        .layer(Extension(Arc::new(Fixtures { root, stub_url })))
+}
+
+// Listens before returning, so that the stub can be requested right away. Binding to port 0
+// picks a free port, which is returned
+pub fn spawn_catalog_stub(root: PathBuf, addr: SocketAddr) -> Result<SocketAddr, Box<dyn Error>> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    info!(
+        "Catalog stub: Serving {} on http://{}",
+        root.display(),
+        addr
+    );
+    let router = catalog_stub_router(root, stub_url(addr));
+    let server = Server::from_tcp(listener)?.serve(router.into_make_service());
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            error!("Catalog stub: {}", err);
+        }
+    });
+    Ok(addr)
+}
+
+// Serves the fixtures of this repository on a free port
+#[cfg(test)]
+pub fn spawn_fixture_stub() -> SocketAddr {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/catalog");
+    spawn_catalog_stub(root, SocketAddr::from(([127, 0, 0, 1], 0))).unwrap()
+}
+
+// Points all catalogs at the stub, under the directories of the fixtures
+pub fn catalog_urls(addr: SocketAddr) -> CatalogUrls {
+    let stub_url = stub_url(addr);
+    CatalogUrls {
+        musicbrainz: format!("{}/musicbrainz/ws/2/", stub_url),
+        cover_art_archive: format!("{}/coverartarchive/release/", stub_url),
+        spotify_token: format!("{}/spotify/api/token", stub_url),
+        spotify_api: format!("{}/spotify/v1/", stub_url),
+        apple_music_api: format!("{}/applemusic/v1/", stub_url),
+    }
+}
+
+fn stub_url(addr: SocketAddr) -> String {
+    format!("http://{}", addr)
+}
+
+async fn fixture(
+    Extension(fixtures): Extension<Arc<Fixtures>>,
+    Path(path): Path<String>,
+) -> Response {
+    let path = path.trim_start_matches('/');
+    let valid = path
+        .split('/')
+        .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+    if !valid {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let root = &fixtures.root;
+    let mut candidates = vec![root.join(path)];
+    candidates.extend(
+        FIXTURE_EXTENSIONS
+            .iter()
+            .map(|extension| root.join(format!("{}.{}", path, extension))),
+    );
+    for candidate in candidates {
+        if let Ok(bytes) = fs::read(&candidate).await {
+            let content_type = content_type(&candidate);
+            let bytes = match content_type {
+                "application/xml" | "application/json" => String::from_utf8_lossy(&bytes)
+                    .replace(STUB_URL_PLACEHOLDER, &fixtures.stub_url)
+                    .into_bytes(),
+                _ => bytes,
+            };
+            return ([(CONTENT_TYPE, content_type)], bytes).into_response();
+        }
+    }
+    warn!("Catalog stub: No fixture for /{}", path);
+    StatusCode::NOT_FOUND.into_response()
+}
+
+fn content_type(path: &std::path::Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("xml") => "application/xml",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
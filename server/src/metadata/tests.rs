@@ -0,0 +1,341 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Runs the fetch job against the catalog stub, see fixtures/catalog/README.md. Needs DATABASE_URL
+// to point at a Postgres server, where each test gets a database of its own
+
+use std::net::SocketAddr;
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, Set, SqlxPostgresConnector,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use entity::sea_orm_active_enums::MetadataState;
+use entity::{
+    isrc_metadata, isrc_metadata_artist_credits, isrc_metadata_genres, isrc_metadata_status,
+};
+
+use crate::metadata::provider::{
+    ArtistCredit, CreditedArtist, MetadataProviders, ProviderKind, TrackMetadata,
+};
+use crate::metadata::ratelimit::RateLimiter;
+use crate::metadata::stub;
+use crate::metadata::{
+    fetch_and_store_metadata, has_changed, isrcs_due_for_retry, FetchDependencies, FetchMetadata,
+    StoredMetadata,
+};
+
+async fn fetch(db: &DatabaseConnection, addr: SocketAddr, isrc: &str) {
+    let providers = MetadataProviders::new(vec![
+        ProviderKind::MusicBrainz.create(RateLimiter::new(None), &stub::catalog_urls(addr))
+    ]);
+    let dependencies = FetchDependencies {
+        db,
+        providers: &providers,
+        mirror: None,
+        pubsub: None,
+    };
+    let metadata = FetchMetadata {
+        isrc: isrc.parse().unwrap(),
+        refresh: false,
+    };
+    assert!(fetch_and_store_metadata(&dependencies, metadata)
+        .await
+        .is_ok());
+}
+
+async fn status(db: &DatabaseConnection, isrc: &str) -> isrc_metadata_status::Model {
+    isrc_metadata_status::Entity::find_by_id(isrc.to_owned())
+        .one(db)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+async fn state(db: &DatabaseConnection, isrc: &str) -> MetadataState {
+    status(db, isrc).await.state
+}
+
+async fn stored(db: &DatabaseConnection, isrc: &str) -> Option<isrc_metadata::Model> {
+    isrc_metadata::Entity::find_by_id(isrc.to_owned())
+        .one(db)
+        .await
+        .unwrap()
+}
+
+async fn credits(db: &DatabaseConnection, isrc: &str) -> Vec<isrc_metadata_artist_credits::Model> {
+    isrc_metadata_artist_credits::Entity::find()
+        .filter(isrc_metadata_artist_credits::Column::Isrc.eq(isrc))
+        .order_by_asc(isrc_metadata_artist_credits::Column::Position)
+        .all(db)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn stores_credits_genres_and_cover_art(pool: PgPool) {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    let addr = stub::spawn_fixture_stub();
+    fetch(&db, addr, "ZZMTF2200001").await;
+
+    assert_eq!(state(&db, "ZZMTF2200001").await, MetadataState::Fetched);
+    let metadata = stored(&db, "ZZMTF2200001").await.unwrap();
+    assert_eq!(metadata.name, "Stub Song");
+    assert_eq!(metadata.artist, "Stub Artist & The Fixtures");
+    assert_eq!(metadata.release_title.as_deref(), Some("Stub Album"));
+    assert_eq!(metadata.length_ms, Some(215000));
+    // Linked back to the stub on the port it was bound to
+    assert_eq!(
+        metadata.cover_art_url,
+        Some(format!(
+            "http://{}/coverartarchive/images/00000000-0000-4000-8000-000000000302-front.png",
+            addr
+        ))
+    );
+
+    let credits = credits(&db, "ZZMTF2200001").await;
+    let names: Vec<&str> = credits.iter().map(|credit| credit.name.as_str()).collect();
+    assert_eq!(names, vec!["Stub Artist", "The Fixtures"]);
+    let genres: Vec<String> = isrc_metadata_genres::Entity::find()
+        .filter(isrc_metadata_genres::Column::Isrc.eq("ZZMTF2200001"))
+        .order_by_asc(isrc_metadata_genres::Column::Position)
+        .all(&db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|genre| genre.genre)
+        .collect();
+    assert_eq!(genres, vec!["pop", "synth-pop"]);
+}
+
+#[sqlx::test]
+async fn records_empty_recording_list_as_not_found(pool: PgPool) {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    fetch(&db, stub::spawn_fixture_stub(), "ZZMTF2200002").await;
+
+    let status = status(&db, "ZZMTF2200002").await;
+    assert_eq!(status.state, MetadataState::NotFound);
+    // Tried again once the catalog may have caught up
+    let retry_in = status.next_retry_at.unwrap() - status.updated_at;
+    assert_eq!(retry_in, Duration::days(1));
+    assert!(stored(&db, "ZZMTF2200002").await.is_none());
+}
+
+#[sqlx::test]
+async fn stores_recording_without_artist_credit(pool: PgPool) {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    fetch(&db, stub::spawn_fixture_stub(), "ZZMTF2200003").await;
+
+    assert_eq!(state(&db, "ZZMTF2200003").await, MetadataState::Fetched);
+    let metadata = stored(&db, "ZZMTF2200003").await.unwrap();
+    assert_eq!(metadata.name, "Uncredited Stub Song");
+    assert_eq!(metadata.artist, "");
+    assert_eq!(
+        metadata.release_title.as_deref(),
+        Some("Uncredited Stub Album")
+    );
+    assert_eq!(metadata.cover_art_url, None);
+    assert!(credits(&db, "ZZMTF2200003").await.is_empty());
+}
+
+#[sqlx::test]
+async fn records_unknown_isrc_as_not_found(pool: PgPool) {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    fetch(&db, stub::spawn_fixture_stub(), "ZZMTF2200004").await;
+
+    assert_eq!(state(&db, "ZZMTF2200004").await, MetadataState::NotFound);
+    assert!(stored(&db, "ZZMTF2200004").await.is_none());
+}
+
+#[sqlx::test]
+async fn ranks_competing_recordings(pool: PgPool) {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    fetch(&db, stub::spawn_fixture_stub(), "ZZMTF2200005").await;
+
+    let metadata = stored(&db, "ZZMTF2200005").await.unwrap();
+    assert_eq!(
+        metadata.recording_mbid,
+        Some(Uuid::parse_str("00000000-0000-4000-8000-000000000113").unwrap())
+    );
+    assert_eq!(metadata.release_title.as_deref(), Some("Competing Album"));
+    assert_eq!(
+        status(&db, "ZZMTF2200005").await.match_reason.as_deref(),
+        Some("Ranked first of 3: 2 releases, official, earliest 2018-05-04")
+    );
+}
+
+#[sqlx::test]
+async fn keeps_pinned_recording(pool: PgPool) {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    let pinned = Uuid::parse_str("00000000-0000-4000-8000-000000000111").unwrap();
+    crate::metadata::status::mark_pending(&db, "ZZMTF2200005".to_owned())
+        .await
+        .unwrap();
+    let mut pending = status(&db, "ZZMTF2200005").await.into_active_model();
+    pending.pinned_recording_mbid = Set(Some(pinned));
+    pending.update(&db).await.unwrap();
+
+    fetch(&db, stub::spawn_fixture_stub(), "ZZMTF2200005").await;
+
+    let metadata = stored(&db, "ZZMTF2200005").await.unwrap();
+    assert_eq!(metadata.recording_mbid, Some(pinned));
+    assert_eq!(
+        metadata.release_title.as_deref(),
+        Some("Competing Song (Promo)")
+    );
+    assert_eq!(
+        status(&db, "ZZMTF2200005").await.match_reason.as_deref(),
+        Some("Pinned recording, of 3")
+    );
+}
+
+async fn insert_status(
+    db: &DatabaseConnection,
+    isrc: &str,
+    state: MetadataState,
+    updated_at: DateTime<FixedOffset>,
+    next_retry_at: Option<DateTime<FixedOffset>>,
+) {
+    isrc_metadata_status::ActiveModel {
+        isrc: Set(isrc.to_owned()),
+        updated_at: Set(updated_at),
+        state: Set(state),
+        attempts: Set(1),
+        last_error: Set(None),
+        next_retry_at: Set(next_retry_at),
+        manual: Set(false),
+        match_reason: Set(None),
+        pinned_recording_mbid: Set(None),
+    }
+    .insert(db)
+    .await
+    .unwrap();
+}
+
+#[sqlx::test]
+async fn retries_not_found_and_failed_when_due(pool: PgPool) {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    let now = Utc::now().with_timezone(&FixedOffset::east(0));
+    let past = Some(now - Duration::minutes(1));
+    let future = Some(now + Duration::days(1));
+    insert_status(&db, "ZZMTF2200001", MetadataState::NotFound, now, past).await;
+    insert_status(&db, "ZZMTF2200002", MetadataState::NotFound, now, future).await;
+    insert_status(&db, "ZZMTF2200003", MetadataState::NotFound, now, None).await;
+    insert_status(&db, "ZZMTF2200004", MetadataState::Failed, now, past).await;
+    insert_status(&db, "ZZMTF2200005", MetadataState::Failed, now, future).await;
+    insert_status(&db, "ZZMTF2200006", MetadataState::Fetched, now, past).await;
+    insert_status(&db, "ZZMTF2200007", MetadataState::Pending, now, None).await;
+    let stuck = now - Duration::hours(2);
+    insert_status(&db, "ZZMTF2200008", MetadataState::Pending, stuck, None).await;
+
+    let mut due = isrcs_due_for_retry(&db, now).await.unwrap();
+    due.sort();
+    assert_eq!(
+        due,
+        vec![
+            "ZZMTF2200001",
+            "ZZMTF2200003",
+            "ZZMTF2200004",
+            "ZZMTF2200008"
+        ]
+    );
+}
+
+const ARTIST_MBID: &str = "00000000-0000-4000-8000-000000000201";
+
+fn track_metadata() -> TrackMetadata {
+    TrackMetadata {
+        name: "Stub Song".to_owned(),
+        artist: "Stub Artist".to_owned(),
+        artist_credits: vec![ArtistCredit {
+            name: "Stub Artist".to_owned(),
+            join_phrase: "".to_owned(),
+            artist: Some(CreditedArtist {
+                mbid: ARTIST_MBID.to_owned(),
+                name: "Stub Artist".to_owned(),
+            }),
+        }],
+        cover_art_url: None,
+        release_title: Some("Stub Album".to_owned()),
+        release_date: Some("2022".to_owned()),
+        length_ms: Some(215000),
+        recording_mbid: None,
+        release_mbid: None,
+        genres: vec!["pop".to_owned(), "synth-pop".to_owned()],
+        match_reason: None,
+    }
+}
+
+// As stored from track_metadata()
+fn stored_metadata() -> StoredMetadata {
+    StoredMetadata {
+        metadata: isrc_metadata::Model {
+            isrc: "ZZMTF2200001".to_owned(),
+            name: "Stub Song".to_owned(),
+            artist: "Stub Artist".to_owned(),
+            cover_art_url: None,
+            release_title: Some("Stub Album".to_owned()),
+            release_date: Some("2022".to_owned()),
+            length_ms: Some(215000),
+            recording_mbid: None,
+            release_mbid: None,
+            cover_art_mirrored_at: None,
+        },
+        credits: vec![isrc_metadata_artist_credits::Model {
+            isrc: "ZZMTF2200001".to_owned(),
+            position: 0,
+            name: "Stub Artist".to_owned(),
+            join_phrase: "".to_owned(),
+            artist_mbid: Some(Uuid::parse_str(ARTIST_MBID).unwrap()),
+        }],
+        genres: vec!["pop".to_owned(), "synth-pop".to_owned()],
+    }
+}
+
+#[test]
+fn unchanged_metadata() {
+    assert!(!has_changed(&stored_metadata(), &track_metadata()));
+}
+
+#[test]
+fn changed_genres() {
+    let mut current = track_metadata();
+    current.genres.reverse();
+    assert!(has_changed(&stored_metadata(), &current));
+    current.genres.pop();
+    assert!(has_changed(&stored_metadata(), &current));
+}
+
+#[test]
+fn changed_credits() {
+    // Same artist line, credited to another artist
+    let mut current = track_metadata();
+    current.artist_credits[0].artist = None;
+    assert!(has_changed(&stored_metadata(), &current));
+
+    let mut current = track_metadata();
+    current.artist_credits.push(ArtistCredit {
+        name: "".to_owned(),
+        join_phrase: "".to_owned(),
+        artist: None,
+    });
+    assert!(has_changed(&stored_metadata(), &current));
+}
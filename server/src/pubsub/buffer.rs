@@ -0,0 +1,247 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{mpsc, Notify};
+
+use crate::pubsub::prelude::OverflowPolicy;
+
+struct State<V> {
+    queue: VecDeque<V>,
+    closed: bool,
+}
+
+struct Shared<V> {
+    state: Mutex<State<V>>,
+    notify: Notify,
+}
+
+impl<V> Shared<V> {
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Delivery {
+    Delivered,
+    DroppedOldest,
+    DroppedNewest,
+    Disconnected,
+    Closed,
+}
+
+// Unlike mpsc, the sending side of the buffer may evict already queued values,
+// which is required to implement OverflowPolicy::DropOldest
+pub struct SubscriberSender<V> {
+    shared: Arc<Shared<V>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+pub struct SubscriberReceiver<V> {
+    shared: Arc<Shared<V>>,
+    subscription_id: i64,
+    // Tells the handler to release the sender once the receiver is gone, even if no
+    // further message is ever published to the subscribed topics
+    released: mpsc::UnboundedSender<i64>,
+}
+
+pub fn channel<V>(
+    capacity: usize,
+    policy: OverflowPolicy,
+    subscription_id: i64,
+    released: mpsc::UnboundedSender<i64>,
+) -> (SubscriberSender<V>, SubscriberReceiver<V>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity),
+            closed: false,
+        }),
+        notify: Notify::new(),
+    });
+    (
+        SubscriberSender {
+            shared: shared.clone(),
+            capacity: capacity.max(1),
+            policy,
+        },
+        SubscriberReceiver {
+            shared,
+            subscription_id,
+            released,
+        },
+    )
+}
+
+impl<V> SubscriberSender<V> {
+    pub fn deliver(&self, value: V) -> Delivery {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.closed {
+            return Delivery::Closed;
+        }
+        let delivery = if state.queue.len() < self.capacity {
+            state.queue.push_back(value);
+            Delivery::Delivered
+        } else {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    state.queue.pop_front();
+                    state.queue.push_back(value);
+                    Delivery::DroppedOldest
+                }
+                OverflowPolicy::DropNewest => return Delivery::DroppedNewest,
+                OverflowPolicy::Disconnect => {
+                    state.closed = true;
+                    Delivery::Disconnected
+                }
+            }
+        };
+        drop(state);
+        self.shared.notify.notify_one();
+        delivery
+    }
+
+    // Replayed history is queued regardless of capacity, since the
+    // subscriber could not have consumed any of it yet
+    pub fn backfill(&self, values: Vec<V>) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+        state.queue.extend(values);
+        drop(state);
+        self.shared.notify.notify_one();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().closed
+    }
+}
+
+impl<V> Drop for SubscriberSender<V> {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+impl<V> SubscriberReceiver<V> {
+    pub async fn recv(&mut self) -> Option<V> {
+        loop {
+            let notified = self.shared.notify.notified();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some(value) = state.queue.pop_front() {
+                    return Some(value);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+}
+
+impl<V> Drop for SubscriberReceiver<V> {
+    fn drop(&mut self) {
+        self.shared.close();
+        // Fails only if the handler has stopped, which releases everything anyway
+        let _ = self.released.send(self.subscription_id);
+    }
+}
+
+impl<V> Debug for SubscriberReceiver<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SubscriberReceiver").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_channel(
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> (
+        SubscriberSender<i32>,
+        SubscriberReceiver<i32>,
+        mpsc::UnboundedReceiver<i64>,
+    ) {
+        let (released_sender, released_receiver) = mpsc::unbounded_channel();
+        let (sender, receiver) = channel(capacity, policy, 7, released_sender);
+        (sender, receiver, released_receiver)
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_keeps_newest_values() {
+        let (sender, mut receiver, _released) = test_channel(2, OverflowPolicy::DropOldest);
+        assert_eq!(sender.deliver(1), Delivery::Delivered);
+        assert_eq!(sender.deliver(2), Delivery::Delivered);
+        assert_eq!(sender.deliver(3), Delivery::DroppedOldest);
+        assert_eq!(receiver.recv().await, Some(2));
+        assert_eq!(receiver.recv().await, Some(3));
+    }
+
+    #[tokio::test]
+    async fn drop_newest_keeps_queued_values() {
+        let (sender, mut receiver, _released) = test_channel(2, OverflowPolicy::DropNewest);
+        sender.deliver(1);
+        sender.deliver(2);
+        assert_eq!(sender.deliver(3), Delivery::DroppedNewest);
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.recv().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn disconnect_closes_after_queued_values() {
+        let (sender, mut receiver, _released) = test_channel(1, OverflowPolicy::Disconnect);
+        sender.deliver(1);
+        assert_eq!(sender.deliver(2), Delivery::Disconnected);
+        assert_eq!(sender.deliver(3), Delivery::Closed);
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn backfill_ignores_capacity() {
+        let (sender, mut receiver, _released) = test_channel(1, OverflowPolicy::DropNewest);
+        sender.backfill(vec![1, 2, 3]);
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.recv().await, Some(2));
+        assert_eq!(receiver.recv().await, Some(3));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_sender_ends_the_receiver() {
+        let (sender, mut receiver, _released) = test_channel(1, OverflowPolicy::DropOldest);
+        drop(sender);
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn dropping_the_receiver_releases_the_subscription() {
+        let (sender, receiver, mut released) = test_channel(1, OverflowPolicy::DropOldest);
+        drop(receiver);
+        assert!(sender.is_closed());
+        assert_eq!(released.recv().await, Some(7));
+    }
+}
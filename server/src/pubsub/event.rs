@@ -0,0 +1,93 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    // Assigned by durable engines when reading the stored event, i.e. only by
+    // RedisStreamPubSubEngine. Always None on the memory, redis and postgres engines
+    #[serde(skip)]
+    pub id: Option<String>,
+    // None if the event was raised by the server itself, e.g. by a background job
+    pub actor_id: Option<Uuid>,
+    pub timestamp: DateTime<Utc>,
+    pub payload: EventPayload,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EventPayload {
+    MotifCreated {
+        motif_id: i32,
+        isrc: String,
+        offset: i32,
+    },
+    MotifDeleted {
+        motif_id: i32,
+    },
+    MotifListened {
+        motif_id: i32,
+    },
+    MotifLiked {
+        motif_id: i32,
+    },
+    CommentCreated {
+        motif_id: i32,
+        comment_id: i32,
+    },
+    CommentDeleted {
+        motif_id: i32,
+        comment_id: i32,
+    },
+    ProfileUpdated {
+        profile_id: Uuid,
+    },
+    ProfileFollowed {
+        profile_id: Uuid,
+    },
+    ProfileUnfollowed {
+        profile_id: Uuid,
+    },
+    MetadataUpdated {
+        isrc: String,
+    },
+    ServiceIdsUpdated {
+        isrc: String,
+    },
+}
+
+impl Event {
+    pub fn new(actor_id: Uuid, payload: EventPayload) -> Self {
+        Self {
+            id: None,
+            actor_id: Some(actor_id),
+            timestamp: Utc::now(),
+            payload,
+        }
+    }
+
+    pub fn system(payload: EventPayload) -> Self {
+        Self {
+            id: None,
+            actor_id: None,
+            timestamp: Utc::now(),
+            payload,
+        }
+    }
+}
@@ -0,0 +1,208 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_graphql::futures_util::future::{abortable, AbortHandle};
+use async_trait::async_trait;
+use log::{error, info};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::pubsub::prelude::PubSubCommand::Incoming;
+use crate::pubsub::prelude::{
+    PubSubCancellationSender, PubSubCommandSender, PubSubConfig, PubSubEngine, PubSubResult, Route,
+};
+use crate::pubsub::private;
+use crate::pubsub::private::PubSubStart;
+
+/// Keeps the topic fan-out in process, for single-node deployments and tests.
+/// Published messages are looped back into the handler as incoming messages.
+pub struct MemoryPubSubEngine<V> {
+    loopback: Option<mpsc::UnboundedSender<(String, V)>>,
+    abort: Option<AbortHandle>,
+}
+
+impl<V: Clone + Debug + Sync + Send + 'static> MemoryPubSubEngine<V> {
+    pub(crate) fn new() -> Box<Mutex<Self>> {
+        Box::new(Mutex::new(Self {
+            loopback: None,
+            abort: None,
+        }))
+    }
+}
+
+#[async_trait]
+impl<V: Clone + Debug + Sync + Send + 'static> PubSubEngine<V> for MemoryPubSubEngine<V> {
+    async fn connect(
+        &mut self,
+        self_ref: Arc<Box<Mutex<dyn PubSubEngine<V> + Send + Sync>>>,
+        config: PubSubConfig,
+    ) -> (PubSubCommandSender<V>, PubSubCancellationSender) {
+        let (command_sender, cancellation_sender) = self.start_handler(self_ref, config).await;
+
+        // Publishing happens from within the handler, so messages must not be sent
+        // back into its (bounded) command channel directly
+        let (loopback_sender, mut loopback_receiver) = mpsc::unbounded_channel::<(String, V)>();
+        let command_sender_copy = command_sender.clone();
+        let (task, handle) = abortable(async move {
+            while let Some((topic, message)) = loopback_receiver.recv().await {
+                info!("Memory: Message received");
+
+                if command_sender_copy
+                    .send(Incoming {
+                        topic,
+                        route: Route::Any,
+                        message,
+                    })
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        self.loopback = Some(loopback_sender);
+        self.abort = Some(handle);
+        tokio::spawn(task);
+
+        info!("Memory: Connected");
+        (command_sender, cancellation_sender)
+    }
+
+    async fn disconnect(&self) {
+        if let Some(abort) = &self.abort {
+            abort.abort();
+        }
+    }
+
+    async fn subscribe_to_topic(&self, topic: String) -> PubSubResult<()> {
+        info!("Memory: Subscribing to topic \"{}\"", topic);
+        Ok(())
+    }
+
+    async fn unsubscribe_from_topic(&self, topic: String) -> PubSubResult<()> {
+        info!("Memory: Unsubscribing from topic \"{}\"", topic);
+        Ok(())
+    }
+
+    async fn publish(&self, topic: String, message: V) {
+        info!("Memory: Publishing message!");
+        match &self.loopback {
+            Some(loopback) => {
+                if loopback.send((topic, message)).is_err() {
+                    error!("Memory: Publish error: Loopback closed");
+                }
+            }
+            None => error!("Memory: Publish error: Not connected"),
+        }
+    }
+}
+
+impl<V: Clone + Debug + Sync + Send + 'static> private::PubSubStart<V> for MemoryPubSubEngine<V> {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::pubsub::prelude::{PubSub, PubSubHandle, PubSubSubscriptionHandle};
+
+    async fn connect() -> (PubSub<String>, PubSubHandle<String>) {
+        let pubsub = PubSub::connect(MemoryPubSubEngine::new(), PubSubConfig::default()).await;
+        let handle = PubSubHandle::from(&pubsub).await;
+        (pubsub, handle)
+    }
+
+    async fn receive(subscription: &mut PubSubSubscriptionHandle<String>) -> Option<String> {
+        timeout(Duration::from_secs(1), subscription.receive())
+            .await
+            .expect("no message received")
+    }
+
+    #[tokio::test]
+    async fn publish_reaches_topic_subscribers() {
+        let (_pubsub, handle) = connect().await;
+        let mut first = handle.subscribe(vec!["a".to_string()]).await.unwrap();
+        let mut second = handle.subscribe(vec!["a".to_string()]).await.unwrap();
+
+        handle.publish("a".to_string(), "1".to_string()).await;
+
+        assert_eq!(receive(&mut first).await, Some("1".to_string()));
+        assert_eq!(receive(&mut second).await, Some("1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn pattern_subscribers_receive_matching_topics() {
+        let (_pubsub, handle) = connect().await;
+        let mut subscription = handle
+            .subscribe_patterns(vec!["MOTIF_CREATED.*".to_string()])
+            .await
+            .unwrap();
+
+        handle
+            .publish("MOTIF_UPDATED.1".to_string(), "1".to_string())
+            .await;
+        handle
+            .publish("MOTIF_CREATED.2".to_string(), "2".to_string())
+            .await;
+
+        // Messages are handled in order, so the first one was not delivered
+        assert_eq!(receive(&mut subscription).await, Some("2".to_string()));
+    }
+
+    #[tokio::test]
+    async fn removed_topics_are_not_delivered() {
+        let (_pubsub, handle) = connect().await;
+        let mut subscription = handle
+            .subscribe(vec!["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+
+        subscription
+            .remove_topics(vec!["a".to_string()])
+            .await
+            .unwrap();
+        handle.publish("a".to_string(), "1".to_string()).await;
+        handle.publish("b".to_string(), "2".to_string()).await;
+
+        assert_eq!(receive(&mut subscription).await, Some("2".to_string()));
+    }
+
+    #[tokio::test]
+    async fn dropped_subscriptions_leave_others_subscribed() {
+        let (_pubsub, handle) = connect().await;
+        let dropped = handle.subscribe(vec!["a".to_string()]).await.unwrap();
+        let mut kept = handle.subscribe(vec!["a".to_string()]).await.unwrap();
+
+        drop(dropped);
+        handle.publish("a".to_string(), "1".to_string()).await;
+
+        assert_eq!(receive(&mut kept).await, Some("1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_pubsub_ends_subscriptions() {
+        let (pubsub, handle) = connect().await;
+        let mut subscription = handle.subscribe(vec!["a".to_string()]).await.unwrap();
+
+        drop(pubsub);
+
+        assert_eq!(receive(&mut subscription).await, None);
+    }
+}
@@ -14,6 +14,12 @@
  * limitations under the License.
  */
 
+mod buffer;
+pub mod event;
+pub mod memory;
+mod pattern;
+pub mod postgres;
 pub mod prelude;
 mod private;
 pub mod redis;
+pub mod redis_streams;
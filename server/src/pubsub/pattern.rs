@@ -0,0 +1,175 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Longer patterns are refused on subscription and never match, which bounds the
+// cost of matching every incoming message against every pattern
+pub const MAX_PATTERN_LEN: usize = 256;
+
+// Matches topics against patterns with the glob syntax of Redis PSUBSCRIBE,
+// so that engines without native pattern support route identically.
+// Backtracks to the last star only, which keeps matching in O(pattern * topic)
+pub fn glob_match(pattern: &str, topic: &str) -> bool {
+    let (pattern, topic) = (pattern.as_bytes(), topic.as_bytes());
+    if pattern.len() > MAX_PATTERN_LEN {
+        return false;
+    }
+    let (mut p, mut t) = (0, 0);
+    // Pattern position after the last star, and the topic position the star extends to
+    let mut star: Option<(usize, usize)> = None;
+    while t < topic.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, t));
+            continue;
+        }
+        if let Some(next) = match_one(pattern, p, topic[t]) {
+            p = next;
+            t += 1;
+            continue;
+        }
+        match star {
+            Some((star_p, star_t)) => {
+                p = star_p;
+                t = star_t + 1;
+                star = Some((star_p, t));
+            }
+            None => return false,
+        }
+    }
+    while pattern.get(p) == Some(&b'*') {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+// The pattern position after the element at p, if that element matches the character
+fn match_one(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+    match &pattern[p..] {
+        [] => None,
+        [b'?', ..] => Some(p + 1),
+        [b'[', class @ ..] => match match_class(class, c) {
+            Some((true, rest)) => Some(pattern.len() - rest.len()),
+            _ => None,
+        },
+        [b'\\', escaped, ..] => (*escaped == c).then(|| p + 2),
+        [literal, ..] => (*literal == c).then(|| p + 1),
+    }
+}
+
+// Returns whether the character is in the class, and the pattern after the
+// closing bracket, or None if the class is unterminated
+fn match_class(class: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negate, mut class) = match class {
+        [b'^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    loop {
+        match class {
+            [] => return None,
+            [b']', rest @ ..] => return Some((matched != negate, rest)),
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == c;
+                class = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (low, high) = if start <= end {
+                    (start, end)
+                } else {
+                    (end, start)
+                };
+                matched |= (*low..=*high).contains(&c);
+                class = rest;
+            }
+            [member, rest @ ..] => {
+                matched |= *member == c;
+                class = rest;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_literals() {
+        assert!(glob_match("MOTIF_CREATED.1", "MOTIF_CREATED.1"));
+        assert!(!glob_match("MOTIF_CREATED.1", "MOTIF_CREATED.12"));
+        assert!(!glob_match("MOTIF_CREATED.12", "MOTIF_CREATED.1"));
+    }
+
+    #[test]
+    fn matches_stars() {
+        assert!(glob_match("MOTIF_CREATED.*", "MOTIF_CREATED.1"));
+        assert!(glob_match("MOTIF_CREATED.*", "MOTIF_CREATED."));
+        assert!(glob_match("*.1", "MOTIF_CREATED.1"));
+        assert!(glob_match("M*D.*1", "MOTIF_CREATED.21"));
+        assert!(glob_match("**", "anything"));
+        assert!(!glob_match("MOTIF_CREATED.*", "MOTIF_DELETED.1"));
+        assert!(!glob_match("*.2", "MOTIF_CREATED.1"));
+    }
+
+    #[test]
+    fn matches_question_marks() {
+        assert!(glob_match("h?llo", "hello"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(!glob_match("h?llo", "heello"));
+    }
+
+    #[test]
+    fn matches_classes() {
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[ae]llo", "hillo"));
+        assert!(glob_match("h[^e]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("h[a-b]llo", "hbllo"));
+        assert!(glob_match("h[b-a]llo", "hbllo"));
+        assert!(!glob_match("h[a-b]llo", "hcllo"));
+        // Unterminated classes never match
+        assert!(!glob_match("h[ae", "ha"));
+    }
+
+    #[test]
+    fn matches_escapes() {
+        assert!(glob_match("a\\*b", "a*b"));
+        assert!(!glob_match("a\\*b", "axb"));
+        assert!(glob_match("a\\?", "a?"));
+        assert!(!glob_match("a\\?", "ab"));
+        assert!(glob_match("h[\\]]llo", "h]llo"));
+    }
+
+    #[test]
+    fn matches_empty_strings() {
+        assert!(glob_match("", ""));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("", "a"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn rejects_non_matching_input_quickly() {
+        let topic = "a".repeat(200);
+        assert!(!glob_match("a*a*a*a*a*a*a*a*a*a*a*a*b", &topic));
+    }
+
+    #[test]
+    fn never_matches_over_long_patterns() {
+        let pattern = "*".repeat(MAX_PATTERN_LEN + 1);
+        assert!(!glob_match(&pattern, "topic"));
+    }
+}
@@ -0,0 +1,209 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_graphql::futures_util::future::{abortable, AbortHandle};
+use async_trait::async_trait;
+use log::{error, info, warn};
+use sqlx::postgres::{PgListener, PgPool};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::sleep;
+
+use crate::pubsub::event::Event;
+use crate::pubsub::prelude::PubSubCommand::Incoming;
+use crate::pubsub::prelude::{
+    PubSubCancellationSender, PubSubCommandSender, PubSubConfig, PubSubEngine, PubSubError,
+    PubSubResult, Route,
+};
+use crate::pubsub::private;
+use crate::pubsub::private::PubSubStart;
+
+// Postgres truncates identifiers, and thereby channel names, beyond this length
+const MAX_CHANNEL_LEN: usize = 63;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+enum ListenCommand {
+    Listen(String),
+    Unlisten(String),
+}
+
+pub struct PostgresPubSubEngine {
+    pool: PgPool,
+    // LISTEN holds on to its connection, so the listener connects on its own instead
+    // of taking one of the pool's connections for good
+    url: String,
+    listen_commands: Option<mpsc::UnboundedSender<ListenCommand>>,
+    abort: Option<AbortHandle>,
+}
+
+impl PostgresPubSubEngine {
+    pub(crate) fn new(pool: PgPool, url: String) -> Box<Mutex<Self>> {
+        Box::new(Mutex::new(Self {
+            pool,
+            url,
+            listen_commands: None,
+            abort: None,
+        }))
+    }
+
+    fn check_channel(topic: &str) -> PubSubResult<()> {
+        if topic.len() > MAX_CHANNEL_LEN {
+            return Err(PubSubError::Engine(format!(
+                "Topic exceeds channel name limit of {}",
+                MAX_CHANNEL_LEN
+            )));
+        }
+        Ok(())
+    }
+
+    fn send_listen_command(&self, command: ListenCommand) -> PubSubResult<()> {
+        match &self.listen_commands {
+            Some(sender) => sender
+                .send(command)
+                .map_err(|_| PubSubError::Engine("Listener task is gone".to_owned())),
+            None => Err(PubSubError::NotConnected),
+        }
+    }
+}
+
+#[async_trait]
+impl PubSubEngine<Event> for PostgresPubSubEngine {
+    async fn connect(
+        &mut self,
+        self_ref: Arc<Box<Mutex<dyn PubSubEngine<Event> + Send + Sync>>>,
+        config: PubSubConfig,
+    ) -> (PubSubCommandSender<Event>, PubSubCancellationSender) {
+        let mut listener = PgListener::connect(&self.url).await.unwrap();
+        info!("Postgres Listener: Connected");
+
+        let (command_sender, cancellation_sender) = self.start_handler(self_ref, config).await;
+        let command_sender_copy = command_sender.clone();
+        let (listen_sender, mut listen_receiver) = mpsc::unbounded_channel::<ListenCommand>();
+        let (task, handle) = abortable(async move {
+            loop {
+                tokio::select! {
+                    command = listen_receiver.recv() => match command {
+                        Some(ListenCommand::Listen(topic)) => {
+                            if let Err(err) = listener.listen(&topic).await {
+                                error!("Postgres: Failed to listen to \"{}\": {}", topic, err);
+                            }
+                        }
+                        Some(ListenCommand::Unlisten(topic)) => {
+                            if let Err(err) = listener.unlisten(&topic).await {
+                                error!("Postgres: Failed to unlisten from \"{}\": {}", topic, err);
+                            }
+                        }
+                        None => break,
+                    },
+                    // PgListener reconnects and re-issues LISTEN for all channels
+                    // on the next call after having returned None
+                    notification = listener.try_recv() => match notification {
+                        Ok(Some(notification)) => {
+                            info!("Postgres: Message received");
+                            let event = match serde_json::from_str::<Event>(notification.payload()) {
+                                Ok(event) => event,
+                                Err(err) => {
+                                    error!("Postgres: Dropping undecodable message: {}", err);
+                                    continue;
+                                }
+                            };
+                            let incoming = Incoming {
+                                topic: notification.channel().to_owned(),
+                                route: Route::Any,
+                                message: event,
+                            };
+                            if command_sender_copy.send(incoming).await.is_err() {
+                                info!("Postgres: Handler gone, stopping listener");
+                                break;
+                            }
+                        }
+                        Ok(None) => warn!("Postgres: Listener connection lost, reconnecting"),
+                        Err(err) => {
+                            error!("Postgres: Listener error: {}", err);
+                            sleep(RECONNECT_DELAY).await;
+                        }
+                    }
+                }
+            }
+        });
+        self.listen_commands = Some(listen_sender);
+        self.abort = Some(handle);
+        tokio::spawn(task);
+
+        (command_sender, cancellation_sender)
+    }
+
+    async fn disconnect(&self) {
+        if let Some(abort) = &self.abort {
+            abort.abort();
+        }
+    }
+
+    async fn subscribe_to_topic(&self, topic: String) -> PubSubResult<()> {
+        info!("Postgres: Subscribing to topic \"{}\"", topic);
+        Self::check_channel(&topic)?;
+        self.send_listen_command(ListenCommand::Listen(topic))
+    }
+
+    async fn unsubscribe_from_topic(&self, topic: String) -> PubSubResult<()> {
+        info!("Postgres: Unsubscribing from topic \"{}\"", topic);
+        self.send_listen_command(ListenCommand::Unlisten(topic))
+    }
+
+    async fn publish(&self, topic: String, message: Event) {
+        info!("Postgres: Publishing message!");
+        // NOTIFY would fail or deliver to a truncated channel no one listens to
+        if let Err(err) = Self::check_channel(&topic) {
+            error!("Postgres: Not publishing to \"{}\": {}", topic, err);
+            return;
+        }
+        let json = match serde_json::to_string(&message) {
+            Ok(json) => json,
+            Err(err) => {
+                error!("Postgres: Failed to encode message: {}", err);
+                return;
+            }
+        };
+        if let Err(err) = sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(topic)
+            .bind(json)
+            .execute(&self.pool)
+            .await
+        {
+            error!("Postgres: Publish error: {}", err);
+        }
+    }
+}
+
+impl private::PubSubStart<Event> for PostgresPubSubEngine {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_channels_up_to_the_limit() {
+        assert!(PostgresPubSubEngine::check_channel(&"a".repeat(MAX_CHANNEL_LEN)).is_ok());
+        assert!(PostgresPubSubEngine::check_channel("MOTIF_CREATED.1").is_ok());
+    }
+
+    #[test]
+    fn rejects_channels_over_the_limit() {
+        assert!(PostgresPubSubEngine::check_channel(&"a".repeat(MAX_CHANNEL_LEN + 1)).is_err());
+    }
+}
@@ -15,8 +15,10 @@
  */
 
 use std::collections::{HashMap, HashSet};
-use std::fmt::Debug;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
 use std::mem::take;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use async_graphql::futures_util::Stream;
@@ -26,10 +28,10 @@ use futures::executor::block_on;
 use log::info;
 use tokio::sync::{mpsc, oneshot, Mutex};
 
-use crate::pubsub::private;
+use crate::pubsub::{buffer, private};
 
-pub type PubSubSender<V> = mpsc::Sender<V>;
-pub type PubSubReceiver<V> = mpsc::Receiver<V>;
+pub type PubSubSender<V> = buffer::SubscriberSender<V>;
+pub type PubSubReceiver<V> = buffer::SubscriberReceiver<V>;
 pub type PubSubCommandSender<V> = mpsc::Sender<PubSubCommand<V>>;
 pub type PubSubHandler<V> = mpsc::Receiver<PubSubCommand<V>>;
 pub type PubSubCancellationSender = oneshot::Sender<()>;
@@ -44,11 +46,95 @@ where
     async fn connect(
         &mut self,
         arc: Arc<Box<Mutex<dyn PubSubEngine<V> + Send + Sync>>>,
+        config: PubSubConfig,
     ) -> (PubSubCommandSender<V>, PubSubCancellationSender);
     async fn disconnect(&self);
-    async fn subscribe_to_topic(&self, topic: String);
-    async fn unsubscribe_from_topic(&self, topic: String);
+    async fn subscribe_to_topic(&self, topic: String) -> PubSubResult<()>;
+    async fn unsubscribe_from_topic(&self, topic: String) -> PubSubResult<()>;
     async fn publish(&self, topic: String, message: V);
+
+    // Engines without native pattern subscriptions route incoming messages with
+    // Route::Any, so that they are matched against patterns by the handler
+    async fn subscribe_to_pattern(&self, _pattern: String) -> PubSubResult<()> {
+        Ok(())
+    }
+    async fn unsubscribe_from_pattern(&self, _pattern: String) -> PubSubResult<()> {
+        Ok(())
+    }
+
+    // Messages published to any of the topics after the given event id,
+    // only supported by engines which retain history
+    async fn history(&self, _topics: Vec<String>, _after_event_id: String) -> Vec<V> {
+        Vec::new()
+    }
+}
+
+pub type PubSubResult<T> = Result<T, PubSubError>;
+
+#[derive(Debug, Clone)]
+pub enum PubSubError {
+    NotConnected,
+    PatternTooLong(usize),
+    Engine(String),
+}
+
+impl Display for PubSubError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PubSubError::NotConnected => write!(f, "PubSub engine not connected"),
+            PubSubError::PatternTooLong(max) => {
+                write!(f, "PubSub pattern longer than {} characters", max)
+            }
+            PubSubError::Engine(message) => write!(f, "PubSub engine error: {}", message),
+        }
+    }
+}
+
+impl Error for PubSubError {}
+
+// Which subscribers an incoming message is routed to
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Route {
+    // Received for an exact topic subscription of the engine
+    Topic,
+    // Received for a pattern subscription of the engine
+    Pattern,
+    Any,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
+    Disconnect,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop_oldest" => Ok(OverflowPolicy::DropOldest),
+            "drop_newest" => Ok(OverflowPolicy::DropNewest),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            other => Err(format!("Unknown overflow policy: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PubSubConfig {
+    pub buffer_size: usize,
+    pub overflow_policy: OverflowPolicy,
+}
+
+impl Default for PubSubConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: 16,
+            overflow_policy: OverflowPolicy::DropOldest,
+        }
+    }
 }
 
 pub struct PubSub<V: Clone + Debug + Sync + Send + 'static> {
@@ -76,16 +162,44 @@ impl<V: Clone + Debug + Sync + Send> PubSubHandle<V> {
             .unwrap();
     }
 
-    pub async fn subscribe(&self, topics: Vec<String>) -> PubSubSubscriptionHandle<V> {
-        PubSubSubscriptionHandle::new(self.command_sender.clone(), topics).await
+    pub async fn subscribe(
+        &self,
+        topics: Vec<String>,
+    ) -> PubSubResult<PubSubSubscriptionHandle<V>> {
+        self.subscribe_after(topics, None).await
+    }
+
+    pub async fn subscribe_after(
+        &self,
+        topics: Vec<String>,
+        after_event_id: Option<String>,
+    ) -> PubSubResult<PubSubSubscriptionHandle<V>> {
+        PubSubSubscriptionHandle::new(
+            self.command_sender.clone(),
+            topics,
+            Vec::new(),
+            after_event_id,
+        )
+        .await
+    }
+
+    // Glob patterns as understood by Redis PSUBSCRIBE, e.g. "MOTIF_CREATED.*"
+    pub async fn subscribe_patterns(
+        &self,
+        patterns: Vec<String>,
+    ) -> PubSubResult<PubSubSubscriptionHandle<V>> {
+        PubSubSubscriptionHandle::new(self.command_sender.clone(), Vec::new(), patterns, None).await
     }
 }
 
 impl<V: Clone + Debug + Sync + Send + 'static> PubSub<V> {
-    pub(crate) async fn connect(engine: Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>) -> Self {
+    pub(crate) async fn connect(
+        engine: Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>,
+        config: PubSubConfig,
+    ) -> Self {
         let arc: Arc<Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>> = Arc::new(engine);
         let mut guard = arc.lock().await;
-        let (register, cancel) = guard.connect(arc.clone()).await;
+        let (register, cancel) = guard.connect(arc.clone(), config).await;
         Self {
             engine: arc.clone(),
             command_sender: register,
@@ -107,13 +221,25 @@ impl<V: Clone + Debug + Sync + Send + 'static> Drop for PubSub<V> {
 pub enum PubSubCommand<V> {
     Subscribe {
         topics: Vec<String>,
-        ans: oneshot::Sender<(i64, PubSubReceiver<V>)>,
+        patterns: Vec<String>,
+        after_event_id: Option<String>,
+        ans: oneshot::Sender<PubSubResult<(i64, PubSubReceiver<V>)>>,
     },
     Unsubscribe {
         subscription_id: i64,
     },
+    AddTopics {
+        subscription_id: i64,
+        topics: Vec<String>,
+        ans: oneshot::Sender<PubSubResult<()>>,
+    },
+    RemoveTopics {
+        subscription_id: i64,
+        topics: Vec<String>,
+    },
     Incoming {
         topic: String,
+        route: Route,
         message: V,
     },
     Outgoing {
@@ -126,29 +252,58 @@ pub enum PubSubCommand<V> {
 pub struct PubSubSubscriptionHandle<V> {
     id: i64,
     receiver: PubSubReceiver<V>,
-    unregister: mpsc::Sender<PubSubCommand<V>>,
+    command_sender: mpsc::Sender<PubSubCommand<V>>,
 }
 
 impl<V: Debug> PubSubSubscriptionHandle<V> {
     async fn new(
         command_sender: PubSubCommandSender<V>,
         topics: Vec<String>,
-    ) -> PubSubSubscriptionHandle<V> {
-        let (ans_sender, ans_recv) = oneshot::channel::<(i64, PubSubReceiver<V>)>();
+        patterns: Vec<String>,
+        after_event_id: Option<String>,
+    ) -> PubSubResult<PubSubSubscriptionHandle<V>> {
+        let (ans_sender, ans_recv) = oneshot::channel();
         command_sender
             .send(PubSubCommand::Subscribe {
-                topics: topics.clone(),
+                topics,
+                patterns,
+                after_event_id,
                 ans: ans_sender,
             })
             .await
-            .unwrap();
-        let (id, receiver) = ans_recv.await.unwrap();
+            .map_err(|_| PubSubError::NotConnected)?;
+        let (id, receiver) = ans_recv.await.map_err(|_| PubSubError::NotConnected)??;
         info!("PubSub: Obtained handle");
-        PubSubSubscriptionHandle {
+        Ok(PubSubSubscriptionHandle {
             id,
             receiver,
-            unregister: command_sender,
-        }
+            command_sender,
+        })
+    }
+
+    // Changes the topic set of the live subscription, messages published to added
+    // topics before the command has been handled are not delivered
+    pub async fn add_topics(&self, topics: Vec<String>) -> PubSubResult<()> {
+        let (ans_sender, ans_recv) = oneshot::channel();
+        self.command_sender
+            .send(PubSubCommand::AddTopics {
+                subscription_id: self.id,
+                topics,
+                ans: ans_sender,
+            })
+            .await
+            .map_err(|_| PubSubError::NotConnected)?;
+        ans_recv.await.map_err(|_| PubSubError::NotConnected)?
+    }
+
+    pub async fn remove_topics(&self, topics: Vec<String>) -> PubSubResult<()> {
+        self.command_sender
+            .send(PubSubCommand::RemoveTopics {
+                subscription_id: self.id,
+                topics,
+            })
+            .await
+            .map_err(|_| PubSubError::NotConnected)
     }
 
     pub async fn receive(&mut self) -> Option<V> {
@@ -169,7 +324,7 @@ impl<V> Drop for PubSubSubscriptionHandle<V> {
         let unsubscribe = PubSubCommand::Unsubscribe {
             subscription_id: self.id,
         };
-        if let None = self.unregister.try_send(unsubscribe).ok() {
+        if let None = self.command_sender.try_send(unsubscribe).ok() {
             info!("PubSub: Failed to release pubsub subscription!");
         }
         {
@@ -182,4 +337,28 @@ pub struct SubscriberMaps<V> {
     pub sub_ids_to_senders: HashMap<i64, PubSubSender<V>>,
     pub sub_ids_to_topics: HashMap<i64, HashSet<String>>,
     pub topics_to_sub_ids: HashMap<String, HashSet<i64>>,
+    pub sub_ids_to_patterns: HashMap<i64, HashSet<String>>,
+    pub patterns_to_sub_ids: HashMap<String, HashSet<i64>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_overflow_policies() {
+        assert_eq!(
+            "drop_oldest".parse::<OverflowPolicy>(),
+            Ok(OverflowPolicy::DropOldest)
+        );
+        assert_eq!(
+            "drop_newest".parse::<OverflowPolicy>(),
+            Ok(OverflowPolicy::DropNewest)
+        );
+        assert_eq!(
+            "disconnect".parse::<OverflowPolicy>(),
+            Ok(OverflowPolicy::Disconnect)
+        );
+        assert!("drop_all".parse::<OverflowPolicy>().is_err());
+    }
 }
@@ -20,12 +20,16 @@ use std::sync::Arc;
 
 use async_graphql::futures_util::future::select;
 use async_trait::async_trait;
-use log::info;
+use log::{error, info, warn};
 use tokio::sync::{mpsc, oneshot, Mutex};
 
+use crate::pubsub::buffer;
+use crate::pubsub::buffer::Delivery;
+use crate::pubsub::pattern::{glob_match, MAX_PATTERN_LEN};
 use crate::pubsub::prelude::{
     PubSubCancellationReceiver, PubSubCancellationSender, PubSubCommand, PubSubCommandSender,
-    PubSubEngine, PubSubHandler, PubSubSender, SubscriberMaps,
+    PubSubConfig, PubSubEngine, PubSubError, PubSubHandler, PubSubResult, PubSubSender, Route,
+    SubscriberMaps,
 };
 
 #[async_trait]
@@ -33,21 +37,195 @@ pub trait PubSubStart<V: Clone + Debug + Sized + Send + Sync + 'static> {
     async fn start_handler(
         &self,
         engine_ref: Arc<Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>>,
+        config: PubSubConfig,
     ) -> (PubSubCommandSender<V>, PubSubCancellationSender)
     where
         Self: Sized,
     {
         let (cancel_sender, cancel_receiver) = oneshot::channel();
         let (register_sender, handler_receiver) = mpsc::channel::<PubSubCommand<V>>(32);
-        pubsub_main(engine_ref, handler_receiver, cancel_receiver).await;
+        pubsub_main(engine_ref, handler_receiver, cancel_receiver, config).await;
         (register_sender, cancel_sender)
     }
 }
 
+async fn add_topics<V>(
+    engine: &Arc<Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>>,
+    subscriber_maps: &mut SubscriberMaps<V>,
+    subscription_id: i64,
+    topics: Vec<String>,
+) -> PubSubResult<()>
+where
+    V: Clone + Debug + Sync + Send + 'static,
+{
+    let sub_topics = subscriber_maps
+        .sub_ids_to_topics
+        .entry(subscription_id)
+        .or_insert_with(HashSet::new);
+    for topic in topics {
+        if !sub_topics.insert(topic.clone()) {
+            continue;
+        }
+        let ids = subscriber_maps
+            .topics_to_sub_ids
+            .entry(topic.clone())
+            .or_insert_with(HashSet::new);
+        if ids.is_empty() {
+            let guard = engine.lock().await;
+            if let Err(err) = guard.subscribe_to_topic(topic.clone()).await {
+                error!(
+                    "PubSub: Failed to subscribe to topic \"{}\": {}",
+                    topic, err
+                );
+                subscriber_maps.topics_to_sub_ids.remove(&topic);
+                sub_topics.remove(&topic);
+                return Err(err);
+            }
+        }
+        ids.insert(subscription_id);
+    }
+    Ok(())
+}
+
+async fn remove_topics<V>(
+    engine: &Arc<Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>>,
+    subscriber_maps: &mut SubscriberMaps<V>,
+    subscription_id: i64,
+    topics: Vec<String>,
+) where
+    V: Clone + Debug + Sync + Send + 'static,
+{
+    let sub_topics = match subscriber_maps.sub_ids_to_topics.get_mut(&subscription_id) {
+        Some(sub_topics) => sub_topics,
+        None => return,
+    };
+    for topic in topics {
+        if !sub_topics.remove(&topic) {
+            continue;
+        }
+        if let Some(topic_subs) = subscriber_maps.topics_to_sub_ids.get_mut(&topic) {
+            topic_subs.remove(&subscription_id);
+            if topic_subs.is_empty() {
+                subscriber_maps.topics_to_sub_ids.remove(&topic);
+                let guard = engine.lock().await;
+                if let Err(err) = guard.unsubscribe_from_topic(topic.clone()).await {
+                    error!(
+                        "PubSub: Failed to unsubscribe from topic \"{}\": {}",
+                        topic, err
+                    );
+                }
+            }
+        }
+    }
+}
+
+async fn add_patterns<V>(
+    engine: &Arc<Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>>,
+    subscriber_maps: &mut SubscriberMaps<V>,
+    subscription_id: i64,
+    patterns: Vec<String>,
+) -> PubSubResult<()>
+where
+    V: Clone + Debug + Sync + Send + 'static,
+{
+    let sub_patterns = subscriber_maps
+        .sub_ids_to_patterns
+        .entry(subscription_id)
+        .or_insert_with(HashSet::new);
+    for pattern in patterns {
+        if pattern.len() > MAX_PATTERN_LEN {
+            return Err(PubSubError::PatternTooLong(MAX_PATTERN_LEN));
+        }
+        if !sub_patterns.insert(pattern.clone()) {
+            continue;
+        }
+        let ids = subscriber_maps
+            .patterns_to_sub_ids
+            .entry(pattern.clone())
+            .or_insert_with(HashSet::new);
+        if ids.is_empty() {
+            let guard = engine.lock().await;
+            if let Err(err) = guard.subscribe_to_pattern(pattern.clone()).await {
+                error!(
+                    "PubSub: Failed to subscribe to pattern \"{}\": {}",
+                    pattern, err
+                );
+                subscriber_maps.patterns_to_sub_ids.remove(&pattern);
+                sub_patterns.remove(&pattern);
+                return Err(err);
+            }
+        }
+        ids.insert(subscription_id);
+    }
+    Ok(())
+}
+
+async fn remove_patterns<V>(
+    engine: &Arc<Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>>,
+    subscriber_maps: &mut SubscriberMaps<V>,
+    subscription_id: i64,
+) where
+    V: Clone + Debug + Sync + Send + 'static,
+{
+    let sub_patterns = match subscriber_maps.sub_ids_to_patterns.remove(&subscription_id) {
+        Some(sub_patterns) => sub_patterns,
+        None => return,
+    };
+    for pattern in sub_patterns {
+        if let Some(pattern_subs) = subscriber_maps.patterns_to_sub_ids.get_mut(&pattern) {
+            pattern_subs.remove(&subscription_id);
+            if pattern_subs.is_empty() {
+                subscriber_maps.patterns_to_sub_ids.remove(&pattern);
+                let guard = engine.lock().await;
+                if let Err(err) = guard.unsubscribe_from_pattern(pattern.clone()).await {
+                    error!(
+                        "PubSub: Failed to unsubscribe from pattern \"{}\": {}",
+                        pattern, err
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn recipients<V>(subscriber_maps: &SubscriberMaps<V>, topic: &str, route: Route) -> HashSet<i64> {
+    let mut sub_ids: HashSet<i64> = HashSet::new();
+    if route != Route::Pattern {
+        if let Some(topic_subs) = subscriber_maps.topics_to_sub_ids.get(topic) {
+            sub_ids.extend(topic_subs);
+        }
+    }
+    if route != Route::Topic {
+        for (pattern, pattern_subs) in &subscriber_maps.patterns_to_sub_ids {
+            if glob_match(pattern, topic) {
+                sub_ids.extend(pattern_subs);
+            }
+        }
+    }
+    sub_ids
+}
+
+async fn remove_subscription<V>(
+    engine: &Arc<Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>>,
+    subscriber_maps: &mut SubscriberMaps<V>,
+    subscription_id: i64,
+) where
+    V: Clone + Debug + Sync + Send + 'static,
+{
+    subscriber_maps.sub_ids_to_senders.remove(&subscription_id);
+    if let Some(topics) = subscriber_maps.sub_ids_to_topics.get(&subscription_id) {
+        let topics = topics.iter().cloned().collect();
+        remove_topics(engine, subscriber_maps, subscription_id, topics).await;
+    }
+    subscriber_maps.sub_ids_to_topics.remove(&subscription_id);
+    remove_patterns(engine, subscriber_maps, subscription_id).await;
+}
+
 async fn pubsub_main<V>(
     engine: Arc<Box<Mutex<dyn PubSubEngine<V> + Sync + Send>>>,
     handler_receiver: PubSubHandler<V>,
     cancellation: PubSubCancellationReceiver,
+    config: PubSubConfig,
 ) where
     V: Clone + Debug + Sync + Send + 'static,
 {
@@ -58,83 +236,152 @@ async fn pubsub_main<V>(
                 sub_ids_to_senders: HashMap::<i64, PubSubSender<V>>::new(),
                 sub_ids_to_topics: HashMap::<i64, HashSet<String>>::new(),
                 topics_to_sub_ids: HashMap::<String, HashSet<i64>>::new(),
+                sub_ids_to_patterns: HashMap::<i64, HashSet<String>>::new(),
+                patterns_to_sub_ids: HashMap::<String, HashSet<i64>>::new(),
             };
 
+            // Receivers report here when dropped, see buffer::SubscriberReceiver
+            let (released_sender, mut released_receiver) = mpsc::unbounded_channel::<i64>();
+
             info!("PubSub: Handler ready to receive commands!");
 
             let mut handler_receiver = handler_receiver;
-            while let Some(command) = handler_receiver.recv().await {
+            loop {
+                let command = tokio::select! {
+                    command = handler_receiver.recv() => match command {
+                        Some(command) => command,
+                        None => break,
+                    },
+                    Some(subscription_id) = released_receiver.recv() => {
+                        remove_subscription(&engine, &mut subscriber_maps, subscription_id).await;
+                        continue;
+                    }
+                };
                 match command {
-                    PubSubCommand::Subscribe { topics, ans } => {
+                    PubSubCommand::Subscribe {
+                        topics,
+                        patterns,
+                        after_event_id,
+                        ans,
+                    } => {
                         info!("PubSub: Subscribe");
                         let subscription_id = sub_id_counter;
                         sub_id_counter += 1;
 
-                        let (sub_sender, sub_receiver) = mpsc::channel::<V>(1);
-                        ans.send((subscription_id, sub_receiver)).unwrap();
-
+                        let (sub_sender, sub_receiver) = buffer::channel::<V>(
+                            config.buffer_size,
+                            config.overflow_policy,
+                            subscription_id,
+                            released_sender.clone(),
+                        );
                         subscriber_maps
                             .sub_ids_to_senders
                             .insert(subscription_id, sub_sender);
-                        subscriber_maps
-                            .sub_ids_to_topics
-                            .insert(subscription_id, HashSet::from_iter(topics.clone()));
-                        for topic in &topics {
-                            let existing = subscriber_maps.topics_to_sub_ids.get_mut(topic);
-                            if let Some(ids) = existing {
-                                if ids.is_empty() {
-                                    {
-                                        let guard = engine.lock().await;
-                                        guard.subscribe_to_topic(topic.clone()).await;
-                                    }
-                                }
-                                ids.insert(subscription_id);
-                            } else {
-                                {
-                                    let guard = engine.lock().await;
-                                    guard.subscribe_to_topic(topic.clone()).await;
-                                }
-                                let mut ids = HashSet::new();
-                                ids.insert(subscription_id);
-                                subscriber_maps.topics_to_sub_ids.insert(topic.clone(), ids);
+                        let mut result = add_topics(
+                            &engine,
+                            &mut subscriber_maps,
+                            subscription_id,
+                            topics.clone(),
+                        )
+                        .await;
+                        if result.is_ok() {
+                            result = add_patterns(
+                                &engine,
+                                &mut subscriber_maps,
+                                subscription_id,
+                                patterns,
+                            )
+                            .await;
+                        }
+                        if let Err(err) = result {
+                            remove_subscription(&engine, &mut subscriber_maps, subscription_id)
+                                .await;
+                            let _ = ans.send(Err(err));
+                            continue;
+                        }
+                        // The receiver is dropped along with a failed answer, which
+                        // releases the subscription again
+                        if ans.send(Ok((subscription_id, sub_receiver))).is_err() {
+                            info!("PubSub: Subscriber gone before registration");
+                            continue;
+                        }
+
+                        if let Some(after_event_id) = after_event_id {
+                            let history = {
+                                let guard = engine.lock().await;
+                                guard.history(topics, after_event_id).await
+                            };
+                            info!("PubSub: Replaying {} messages", history.len());
+                            if let Some(sender) =
+                                subscriber_maps.sub_ids_to_senders.get(&subscription_id)
+                            {
+                                sender.backfill(history);
                             }
                         }
                     }
                     PubSubCommand::Unsubscribe { subscription_id } => {
                         info!("PubSub: Unsubscribe");
-                        subscriber_maps.sub_ids_to_senders.remove(&subscription_id);
-                        if let Some(topics) =
-                            subscriber_maps.sub_ids_to_topics.remove(&subscription_id)
+                        remove_subscription(&engine, &mut subscriber_maps, subscription_id).await;
+                    }
+                    PubSubCommand::AddTopics {
+                        subscription_id,
+                        topics,
+                        ans,
+                    } => {
+                        info!("PubSub: Add topics");
+                        let mut result = Ok(());
+                        if subscriber_maps
+                            .sub_ids_to_senders
+                            .contains_key(&subscription_id)
                         {
-                            for topic in topics {
-                                if let Some(topic_subs) =
-                                    subscriber_maps.topics_to_sub_ids.get_mut(&topic)
-                                {
-                                    topic_subs.remove(&subscription_id);
-                                    if topic_subs.is_empty() {
-                                        {
-                                            let guard = engine.lock().await;
-                                            guard.unsubscribe_from_topic(topic.clone()).await;
-                                        }
-                                    }
-                                }
-                            }
+                            result =
+                                add_topics(&engine, &mut subscriber_maps, subscription_id, topics)
+                                    .await;
                         }
+                        let _ = ans.send(result);
+                    }
+                    PubSubCommand::RemoveTopics {
+                        subscription_id,
+                        topics,
+                    } => {
+                        info!("PubSub: Remove topics");
+                        remove_topics(&engine, &mut subscriber_maps, subscription_id, topics).await;
                     }
                     PubSubCommand::Outgoing { topic, message } => {
                         info!("PubSub: Outgoing message");
                         let guard = engine.lock().await;
                         guard.publish(topic, message).await;
                     }
-                    PubSubCommand::Incoming { topic, message } => {
+                    PubSubCommand::Incoming {
+                        topic,
+                        route,
+                        message,
+                    } => {
                         info!("PubSub: Incoming message");
-                        if let Some(sub_ids) = subscriber_maps.topics_to_sub_ids.get(&topic) {
-                            for dist in sub_ids {
-                                if let Some(sender) = subscriber_maps.sub_ids_to_senders.get(dist) {
-                                    sender.try_send(message.clone()).unwrap();
+                        let mut to_remove: Vec<i64> = Vec::new();
+                        for dist in recipients(&subscriber_maps, &topic, route) {
+                            if let Some(sender) = subscriber_maps.sub_ids_to_senders.get(&dist) {
+                                match sender.deliver(message.clone()) {
+                                    Delivery::Delivered => {}
+                                    Delivery::DroppedOldest => {
+                                        warn!("PubSub: {} lagging, dropped oldest", dist)
+                                    }
+                                    Delivery::DroppedNewest => {
+                                        warn!("PubSub: {} lagging, dropped newest", dist)
+                                    }
+                                    Delivery::Disconnected => {
+                                        warn!("PubSub: {} lagging, disconnecting", dist);
+                                        to_remove.push(dist);
+                                    }
+                                    Delivery::Closed => to_remove.push(dist),
                                 }
                             }
                         }
+                        for subscription_id in to_remove {
+                            info!("PubSub: Releasing closed subscription");
+                            remove_subscription(&engine, &mut subscriber_maps, subscription_id)
+                                .await;
+                        }
                     }
                 }
             }
@@ -16,16 +16,21 @@
 
 use std::sync::Arc;
 
-use async_graphql::futures_util::future::{abortable, AbortHandle};
+use async_graphql::futures_util::future::{abortable, join, AbortHandle};
 use async_trait::async_trait;
 use fred::clients::{RedisClient, SubscriberClient};
 use fred::prelude::{ClientLike, PubsubInterface, ReconnectPolicy, RedisConfig};
 use fred::types::RedisValue;
-use log::{error, info};
+use log::{error, info, warn};
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 
+use crate::pubsub::event::Event;
 use crate::pubsub::prelude::PubSubCommand::Incoming;
-use crate::pubsub::prelude::{PubSubCancellationSender, PubSubCommandSender, PubSubEngine};
+use crate::pubsub::prelude::{
+    PubSubCancellationSender, PubSubCommandSender, PubSubConfig, PubSubEngine, PubSubError,
+    PubSubResult, Route,
+};
 use crate::pubsub::private;
 use crate::pubsub::private::PubSubStart;
 use crate::{pin_mut, StreamExt};
@@ -33,7 +38,13 @@ use crate::{pin_mut, StreamExt};
 pub struct RedisPubSubEngine {
     url: String,
     subscriber: Arc<SubscriberClient>,
+    // Redis delivers a message once per matching subscription and fred drops the
+    // pattern it matched, so patterns live on their own connection to tell them apart
+    pattern_subscriber: Arc<SubscriberClient>,
     publisher: Arc<RedisClient>,
+    // Tasks of fred resubscribing to the tracked channels and patterns after
+    // reconnecting, since a new connection starts out without any
+    managers: Vec<JoinHandle<()>>,
     abort: Option<AbortHandle>,
 }
 
@@ -41,77 +52,122 @@ impl RedisPubSubEngine {
     pub(crate) async fn new(url: String) -> Box<Mutex<Self>> {
         let config = RedisConfig::from_url(&url).unwrap();
         let subscriber = Arc::new(SubscriberClient::new(config.clone()));
+        let pattern_subscriber = Arc::new(SubscriberClient::new(config.clone()));
         let publisher = Arc::new(RedisClient::new(config));
         Box::new(Mutex::new(Self {
             url,
             subscriber,
+            pattern_subscriber,
             publisher,
+            managers: Vec::new(),
             abort: None,
         }))
     }
 }
 
 #[async_trait]
-impl PubSubEngine<RedisValue> for RedisPubSubEngine {
+impl PubSubEngine<Event> for RedisPubSubEngine {
     async fn connect(
         &mut self,
-        self_ref: Arc<Box<Mutex<dyn PubSubEngine<RedisValue> + Send + Sync>>>,
-    ) -> (PubSubCommandSender<RedisValue>, PubSubCancellationSender) {
+        self_ref: Arc<Box<Mutex<dyn PubSubEngine<Event> + Send + Sync>>>,
+        config: PubSubConfig,
+    ) -> (PubSubCommandSender<Event>, PubSubCancellationSender) {
         self.subscriber.connect(Some(ReconnectPolicy::default()));
         self.subscriber.wait_for_connect().await.unwrap();
         info!("Redis Subscriber: Connected to {}", self.url);
+        self.pattern_subscriber
+            .connect(Some(ReconnectPolicy::default()));
+        self.pattern_subscriber.wait_for_connect().await.unwrap();
+        info!("Redis Pattern Subscriber: Connected to {}", self.url);
         self.publisher.connect(Some(ReconnectPolicy::default()));
         self.publisher.wait_for_connect().await.unwrap();
         info!("Redis Publisher: Connected to {}", self.url);
 
-        let (command_sender, cancellation_sender) = self.start_handler(self_ref).await;
-        let command_sender_copy = command_sender.clone();
-        let redis_copy = self.subscriber.clone();
-        let (task, handle) = abortable(async move {
-            let stream = (&redis_copy).on_message();
-
-            pin_mut!(stream);
-            while let Some((channel, message)) = stream.next().await {
-                info!("Redis: Message received");
-
-                command_sender_copy
-                    .clone()
-                    .send(Incoming {
-                        topic: channel,
-                        message: message.clone(),
-                    })
-                    .await
-                    .unwrap();
-            }
-        });
+        let (command_sender, cancellation_sender) = self.start_handler(self_ref, config).await;
+        self.managers = vec![
+            self.subscriber.manage_subscriptions(),
+            self.pattern_subscriber.manage_subscriptions(),
+        ];
+        let (task, handle) = abortable(join(
+            forward_messages(
+                self.subscriber.clone(),
+                command_sender.clone(),
+                Route::Topic,
+            ),
+            forward_messages(
+                self.pattern_subscriber.clone(),
+                command_sender.clone(),
+                Route::Pattern,
+            ),
+        ));
         self.abort = Some(handle);
         tokio::spawn(task);
 
         (command_sender, cancellation_sender)
     }
     async fn disconnect(&self) {
+        if let Some(abort) = &self.abort {
+            abort.abort();
+        }
+        for manager in &self.managers {
+            manager.abort();
+        }
+        if let Err(err) = self.subscriber.quit().await {
+            warn!("Redis: Failed to quit subscriber: {}", err);
+        }
+        if let Err(err) = self.pattern_subscriber.quit().await {
+            warn!("Redis: Failed to quit pattern subscriber: {}", err);
+        }
+        if let Err(err) = self.publisher.quit().await {
+            warn!("Redis: Failed to quit publisher: {}", err);
+        }
+    }
+
+    async fn subscribe_to_topic(&self, topic: String) -> PubSubResult<()> {
+        info!("Redis: Subscribing to topic \"{}\"", topic);
         self.subscriber
-            .quit()
+            .subscribe(topic)
             .await
-            .expect("Redis: Failed to quit subscriber");
-        self.publisher
-            .quit()
+            .map(|_| ())
+            .map_err(|err| PubSubError::Engine(err.to_string()))
+    }
+
+    async fn unsubscribe_from_topic(&self, topic: String) -> PubSubResult<()> {
+        info!("Redis: Unsubscribing from topic \"{}\"", topic);
+        self.subscriber
+            .unsubscribe(topic)
             .await
-            .expect("Redis: Failed to quit publisher");
+            .map(|_| ())
+            .map_err(|err| PubSubError::Engine(err.to_string()))
     }
 
-    async fn subscribe_to_topic(&self, topic: String) {
-        info!("Redis: Subscribing to topic \"{}\"", topic);
-        self.subscriber.subscribe(topic).await.unwrap();
+    async fn subscribe_to_pattern(&self, pattern: String) -> PubSubResult<()> {
+        info!("Redis: Subscribing to pattern \"{}\"", pattern);
+        self.pattern_subscriber
+            .psubscribe(pattern)
+            .await
+            .map(|_| ())
+            .map_err(|err| PubSubError::Engine(err.to_string()))
     }
 
-    async fn unsubscribe_from_topic(&self, topic: String) {
-        info!("Redis: Unsubscribing from topic \"{}\"", topic);
-        self.subscriber.unsubscribe(topic).await.unwrap();
+    async fn unsubscribe_from_pattern(&self, pattern: String) -> PubSubResult<()> {
+        info!("Redis: Unsubscribing from pattern \"{}\"", pattern);
+        self.pattern_subscriber
+            .punsubscribe(pattern)
+            .await
+            .map(|_| ())
+            .map_err(|err| PubSubError::Engine(err.to_string()))
     }
 
-    async fn publish(&self, topic: String, message: RedisValue) {
+    async fn publish(&self, topic: String, message: Event) {
         info!("Redis: Publishing message!");
+        let message = match serde_json::to_string(&message) {
+            Ok(json) => RedisValue::String(json.into()),
+            Err(err) => {
+                error!("Redis: Failed to encode message: {}", err);
+                return;
+            }
+        };
         if let Some(err) = self
             .publisher
             .publish::<i32, _, _>(topic, message)
@@ -123,4 +179,41 @@ impl PubSubEngine<RedisValue> for RedisPubSubEngine {
     }
 }
 
-impl private::PubSubStart<RedisValue> for RedisPubSubEngine {}
+impl private::PubSubStart<Event> for RedisPubSubEngine {}
+
+async fn forward_messages(
+    subscriber: Arc<SubscriberClient>,
+    command_sender: PubSubCommandSender<Event>,
+    route: Route,
+) {
+    let stream = subscriber.on_message();
+
+    pin_mut!(stream);
+    while let Some((channel, message)) = stream.next().await {
+        info!("Redis: Message received");
+
+        let event = match decode_event(&message) {
+            Ok(event) => event,
+            Err(err) => {
+                error!("Redis: Dropping undecodable message: {}", err);
+                continue;
+            }
+        };
+        let incoming = Incoming {
+            topic: channel,
+            route,
+            message: event,
+        };
+        if command_sender.send(incoming).await.is_err() {
+            info!("Redis: Handler stopped, no longer forwarding messages");
+            return;
+        }
+    }
+}
+
+fn decode_event(message: &RedisValue) -> Result<Event, String> {
+    let json = message
+        .as_str()
+        .ok_or_else(|| "Expected string payload".to_owned())?;
+    serde_json::from_str(&json).map_err(|err| err.to_string())
+}
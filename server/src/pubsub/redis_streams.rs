@@ -0,0 +1,330 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_graphql::futures_util::future::{abortable, AbortHandle};
+use async_trait::async_trait;
+use fred::clients::RedisClient;
+use fred::interfaces::StreamsInterface;
+use fred::prelude::{ClientLike, ReconnectPolicy, RedisConfig};
+use fred::types::XReadResponse;
+use log::{error, info};
+use tokio::sync::{Mutex, Notify};
+use tokio::time::sleep;
+
+use crate::pubsub::event::Event;
+use crate::pubsub::prelude::PubSubCommand::Incoming;
+use crate::pubsub::prelude::{
+    PubSubCancellationSender, PubSubCommandSender, PubSubConfig, PubSubEngine, PubSubError,
+    PubSubResult, Route,
+};
+use crate::pubsub::private;
+use crate::pubsub::private::PubSubStart;
+
+const KEY_PREFIX: &str = "pubsub:";
+const EVENT_FIELD: &str = "event";
+const READ_COUNT: u64 = 100;
+const READ_BLOCK_MS: u64 = 1000;
+// Replay reads the history in pages, so that resuming from an old id does not pull
+// the whole stream in a single reply
+const HISTORY_PAGE_SIZE: u64 = 100;
+
+type StreamEntry = (String, HashMap<String, String>);
+
+// Durable alternative to RedisPubSubEngine: every topic is a capped stream, so that
+// subscribers can replay the events they missed while disconnected.
+// Delivery is at-least-once, clients should deduplicate by event id.
+pub struct RedisStreamPubSubEngine {
+    url: String,
+    reader: Arc<RedisClient>,
+    client: Arc<RedisClient>,
+    max_len: i64,
+    cursors: Arc<std::sync::Mutex<HashMap<String, String>>>,
+    wakeup: Arc<Notify>,
+    abort: Option<AbortHandle>,
+}
+
+impl RedisStreamPubSubEngine {
+    pub(crate) async fn new(url: String, max_len: i64) -> Box<Mutex<Self>> {
+        let config = RedisConfig::from_url(&url).unwrap();
+        // XREAD BLOCK occupies its connection, so reading and writing use separate clients
+        let reader = Arc::new(RedisClient::new(config.clone()));
+        let client = Arc::new(RedisClient::new(config));
+        Box::new(Mutex::new(Self {
+            url,
+            reader,
+            client,
+            max_len,
+            cursors: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            wakeup: Arc::new(Notify::new()),
+            abort: None,
+        }))
+    }
+}
+
+fn stream_key(topic: &str) -> String {
+    format!("{}{}", KEY_PREFIX, topic)
+}
+
+fn parse_entry_id(id: &str) -> (u64, u64) {
+    let (millis, seq) = id.split_once('-').unwrap_or((id, "0"));
+    (millis.parse().unwrap_or(0), seq.parse().unwrap_or(0))
+}
+
+fn decode_entry(entry: StreamEntry) -> Result<Event, String> {
+    let (id, mut fields) = entry;
+    let json = fields
+        .remove(EVENT_FIELD)
+        .ok_or_else(|| format!("Entry {} has no event field", id))?;
+    let mut event: Event = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+    event.id = Some(id);
+    Ok(event)
+}
+
+#[async_trait]
+impl PubSubEngine<Event> for RedisStreamPubSubEngine {
+    async fn connect(
+        &mut self,
+        self_ref: Arc<Box<Mutex<dyn PubSubEngine<Event> + Send + Sync>>>,
+        config: PubSubConfig,
+    ) -> (PubSubCommandSender<Event>, PubSubCancellationSender) {
+        self.reader.connect(Some(ReconnectPolicy::default()));
+        self.reader.wait_for_connect().await.unwrap();
+        info!("Redis Stream Reader: Connected to {}", self.url);
+        self.client.connect(Some(ReconnectPolicy::default()));
+        self.client.wait_for_connect().await.unwrap();
+        info!("Redis Stream Client: Connected to {}", self.url);
+
+        let (command_sender, cancellation_sender) = self.start_handler(self_ref, config).await;
+        let command_sender_copy = command_sender.clone();
+        let reader = self.reader.clone();
+        let cursors = self.cursors.clone();
+        let wakeup = self.wakeup.clone();
+        let (task, handle) = abortable(async move {
+            loop {
+                let (keys, ids): (Vec<String>, Vec<String>) = {
+                    let cursors = cursors.lock().unwrap();
+                    cursors
+                        .iter()
+                        .map(|(topic, id)| (stream_key(topic), id.clone()))
+                        .unzip()
+                };
+                if keys.is_empty() {
+                    wakeup.notified().await;
+                    continue;
+                }
+
+                let response: XReadResponse<String, String, String, String> = match reader
+                    .xread_map(Some(READ_COUNT), Some(READ_BLOCK_MS), keys, ids)
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(err) => {
+                        error!("Redis Stream: Read error: {}", err);
+                        sleep(Duration::from_millis(READ_BLOCK_MS)).await;
+                        continue;
+                    }
+                };
+
+                for (key, entries) in response {
+                    let topic = key.trim_start_matches(KEY_PREFIX).to_owned();
+                    for entry in entries {
+                        {
+                            let mut cursors = cursors.lock().unwrap();
+                            match cursors.get_mut(&topic) {
+                                Some(cursor) => *cursor = entry.0.clone(),
+                                // Unsubscribed while reading
+                                None => break,
+                            }
+                        }
+                        info!("Redis Stream: Message received");
+                        match decode_entry(entry) {
+                            Ok(event) => {
+                                let incoming = Incoming {
+                                    topic: topic.clone(),
+                                    route: Route::Any,
+                                    message: event,
+                                };
+                                if command_sender_copy.send(incoming).await.is_err() {
+                                    info!("Redis Stream: Handler gone, stopping reader");
+                                    return;
+                                }
+                            }
+                            Err(err) => error!("Redis Stream: Dropping entry: {}", err),
+                        }
+                    }
+                }
+            }
+        });
+        self.abort = Some(handle);
+        tokio::spawn(task);
+
+        (command_sender, cancellation_sender)
+    }
+
+    async fn disconnect(&self) {
+        if let Some(abort) = &self.abort {
+            abort.abort();
+        }
+        self.reader
+            .quit()
+            .await
+            .expect("Redis Stream: Failed to quit reader");
+        self.client
+            .quit()
+            .await
+            .expect("Redis Stream: Failed to quit client");
+    }
+
+    async fn subscribe_to_topic(&self, topic: String) -> PubSubResult<()> {
+        info!("Redis Stream: Subscribing to topic \"{}\"", topic);
+        // Start reading after the newest entry, older ones are only replayed on request
+        let newest: Result<Vec<StreamEntry>, _> = self
+            .client
+            .xrevrange(stream_key(&topic), "+", "-", Some(1))
+            .await;
+        // Nothing is read for the topic on error, as the subscription is rolled back
+        let cursor = newest
+            .map_err(|err| PubSubError::Engine(err.to_string()))?
+            .first()
+            .map(|(id, _)| id.clone())
+            .unwrap_or("0-0".to_owned());
+        self.cursors.lock().unwrap().insert(topic, cursor);
+        self.wakeup.notify_one();
+        Ok(())
+    }
+
+    async fn unsubscribe_from_topic(&self, topic: String) -> PubSubResult<()> {
+        info!("Redis Stream: Unsubscribing from topic \"{}\"", topic);
+        self.cursors.lock().unwrap().remove(&topic);
+        Ok(())
+    }
+
+    async fn publish(&self, topic: String, message: Event) {
+        info!("Redis Stream: Publishing message!");
+        let json = match serde_json::to_string(&message) {
+            Ok(json) => json,
+            Err(err) => {
+                error!("Redis Stream: Failed to encode message: {}", err);
+                return;
+            }
+        };
+        if let Some(err) = self
+            .client
+            .xadd::<String, _, _, _, _>(
+                stream_key(&topic),
+                false,
+                ("MAXLEN", "~", self.max_len),
+                "*",
+                vec![(EVENT_FIELD, json)],
+            )
+            .await
+            .err()
+        {
+            error!("Redis Stream: Publish error: {}", err);
+        }
+    }
+
+    async fn history(&self, topics: Vec<String>, after_event_id: String) -> Vec<Event> {
+        let mut events: Vec<Event> = Vec::new();
+        for topic in topics {
+            let mut start = format!("({}", after_event_id);
+            loop {
+                let entries: Vec<StreamEntry> = match self
+                    .client
+                    .xrange(
+                        stream_key(&topic),
+                        start.clone(),
+                        "+",
+                        Some(HISTORY_PAGE_SIZE),
+                    )
+                    .await
+                {
+                    Ok(entries) => entries,
+                    Err(err) => {
+                        error!(
+                            "Redis Stream: Failed to read history of \"{}\": {}",
+                            topic, err
+                        );
+                        break;
+                    }
+                };
+                let complete = (entries.len() as u64) < HISTORY_PAGE_SIZE;
+                if let Some((id, _)) = entries.last() {
+                    start = format!("({}", id);
+                }
+                for entry in entries {
+                    match decode_entry(entry) {
+                        Ok(event) => events.push(event),
+                        Err(err) => error!("Redis Stream: Dropping entry: {}", err),
+                    }
+                }
+                if complete {
+                    break;
+                }
+            }
+        }
+        events.sort_by_key(|event| parse_entry_id(event.id.as_deref().unwrap_or("0-0")));
+        events
+    }
+}
+
+impl private::PubSubStart<Event> for RedisStreamPubSubEngine {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pubsub::event::EventPayload;
+
+    #[test]
+    fn parses_entry_ids() {
+        assert_eq!(parse_entry_id("1668000000000-3"), (1668000000000, 3));
+        assert_eq!(parse_entry_id("1668000000000"), (1668000000000, 0));
+        assert_eq!(parse_entry_id("garbage"), (0, 0));
+    }
+
+    #[test]
+    fn entry_ids_order_numerically() {
+        assert!(parse_entry_id("999-0") < parse_entry_id("1000-0"));
+        assert!(parse_entry_id("1000-9") < parse_entry_id("1000-10"));
+    }
+
+    #[test]
+    fn decodes_entries_with_their_id() {
+        let json =
+            serde_json::to_string(&Event::system(EventPayload::MotifDeleted { motif_id: 3 }))
+                .unwrap();
+        let entry = (
+            "1668000000000-0".to_owned(),
+            HashMap::from([(EVENT_FIELD.to_owned(), json)]),
+        );
+        let event = decode_entry(entry).unwrap();
+        assert_eq!(event.id.as_deref(), Some("1668000000000-0"));
+        assert!(matches!(
+            event.payload,
+            EventPayload::MotifDeleted { motif_id: 3 }
+        ));
+    }
+
+    #[test]
+    fn rejects_entries_without_event() {
+        let entry = ("1668000000000-0".to_owned(), HashMap::new());
+        assert!(decode_entry(entry).is_err());
+    }
+}
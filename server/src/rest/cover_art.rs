@@ -0,0 +1,60 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use axum::extract::Path;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Extension, Router};
+use log::error;
+
+use crate::metadata::artwork::{cover_art_key, CoverArtSize};
+use crate::rest::util::{ApiError, DataError, GeneralError};
+use crate::storage::{Storage, StorageError};
+
+// A changed cover is mirrored again under the same key, so it is only cached for a day
+const CACHE_CONTROL_VALUE: &str = "public, max-age=86400";
+
+pub fn cover_art_router() -> Router {
+    Router::new().route("/:isrc/:size", get(cover_art))
+}
+
+async fn cover_art(
+    Extension(storage): Extension<Storage>,
+    Path((isrc, size)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let size = size.parse::<CoverArtSize>().map_err(DataError::NotFound)?;
+    let not_found = || DataError::NotFound("Cover art not found".to_owned());
+    let object = storage
+        .get(&cover_art_key(&isrc, size))
+        .await
+        .map_err(|err| match err {
+            // No cover art is ever stored under an ISRC that makes for an invalid key
+            StorageError::InvalidKey(_) => ApiError::from(not_found()),
+            err => {
+                error!("Failed to read cover art for ISRC {}: {}", isrc, err);
+                GeneralError::Internal.into()
+            }
+        })?
+        .ok_or_else(not_found)?;
+    Ok((
+        [
+            (CONTENT_TYPE, object.content_type),
+            (CACHE_CONTROL, CACHE_CONTROL_VALUE.to_owned()),
+        ],
+        object.bytes,
+    ))
+}
@@ -0,0 +1,66 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use apalis::redis::RedisStorage;
+use axum::extract::Query;
+use axum::middleware::from_fn;
+use axum::routing::get;
+use axum::{Extension, Json, Router};
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+
+use crate::domain::link::datasource;
+use crate::domain::link::typedef::{ResolvedLink, ServiceLink};
+use crate::metadata::provider::ServiceIdProviders;
+use crate::metadata::service_ids::BackfillServiceIds;
+use crate::metadata::FetchMetadata;
+use crate::rest::auth::middleware::verify_jwt_middleware;
+use crate::rest::util::{ApiError, DataError};
+
+pub fn link_router() -> Router {
+    Router::new().route(
+        "/resolve",
+        get(resolve).layer(from_fn(verify_jwt_middleware)),
+    )
+}
+
+#[derive(Deserialize)]
+struct ResolveQuery {
+    link: String,
+}
+
+// GET /links/resolve?link=https://open.spotify.com/track/...
+async fn resolve(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(providers): Extension<ServiceIdProviders>,
+    Extension(metadata_job_storage): Extension<RedisStorage<FetchMetadata>>,
+    Extension(service_id_job_storage): Extension<RedisStorage<BackfillServiceIds>>,
+    Query(query): Query<ResolveQuery>,
+) -> Result<Json<ResolvedLink>, ApiError> {
+    let link = query
+        .link
+        .parse::<ServiceLink>()
+        .map_err(DataError::Invalid)?;
+    let resolved = datasource::resolve_link(
+        &db,
+        &providers,
+        &metadata_job_storage,
+        &service_id_job_storage,
+        link,
+    )
+    .await?;
+    Ok(Json(resolved))
+}
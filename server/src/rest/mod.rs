@@ -17,10 +17,17 @@
 use axum::Router;
 
 use crate::rest::auth::auth_router;
+use crate::rest::cover_art::cover_art_router;
+use crate::rest::link::link_router;
 
 pub mod auth;
+pub mod cover_art;
+pub mod link;
 pub mod util;
 
 pub fn rest_router() -> Router {
-    Router::new().nest("/auth", auth_router())
+    Router::new()
+        .nest("/auth", auth_router())
+        .nest("/cover-art", cover_art_router())
+        .nest("/links", link_router())
 }
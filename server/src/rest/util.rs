@@ -46,6 +46,7 @@ pub enum GeneralError {
 #[derive(Debug, Clone)]
 pub enum DataError {
     NotFound(String),
+    Invalid(String),
 }
 
 #[derive(Debug, Clone)]
@@ -110,6 +111,7 @@ impl Display for DataError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             DataError::NotFound(message) => write!(f, "Data error: {}", message),
+            DataError::Invalid(message) => write!(f, "Data error: {}", message),
         }
     }
 }
@@ -151,6 +153,7 @@ impl IntoResponse for ApiError {
             }
             ApiError::Authorization(_) => StatusCode::UNAUTHORIZED,
             ApiError::Data(DataError::NotFound(_)) => StatusCode::NOT_FOUND,
+            ApiError::Data(DataError::Invalid(_)) => StatusCode::BAD_REQUEST,
             ApiError::General(GeneralError::Database(_)) => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::General(GeneralError::Internal) => StatusCode::INTERNAL_SERVER_ERROR,
         };
@@ -0,0 +1,87 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use tokio::fs;
+
+use crate::storage::{StorageBackend, StorageResult, StoredObject};
+
+// Content types are kept next to each object, since the key need not carry an extension
+const CONTENT_TYPE_SUFFIX: &str = ".content-type";
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    fn content_type_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{}{}", key, CONTENT_TYPE_SUFFIX))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn put(&self, key: &str, object: StoredObject) -> StorageResult<()> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // Written to a temporary file first, so that readers never see partial objects
+        let tmp_path = self.root.join(format!("{}.tmp", key));
+        fs::write(&tmp_path, &object.bytes).await?;
+        fs::write(self.content_type_path(key), object.content_type).await?;
+        fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> StorageResult<Option<StoredObject>> {
+        let bytes = match fs::read(self.path(key)).await {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let content_type = match fs::read_to_string(self.content_type_path(key)).await {
+            Ok(content_type) => content_type,
+            Err(err) if err.kind() == ErrorKind::NotFound => "application/octet-stream".to_owned(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(StoredObject {
+            content_type,
+            bytes,
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        for path in [self.path(key), self.content_type_path(key)] {
+            match fs::remove_file(path).await {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+/*
+ * Copyright 2022 Julian Ostarek
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+pub mod local;
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+#[derive(Debug)]
+pub enum StorageError {
+    InvalidKey(String),
+    Io(io::Error),
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::InvalidKey(key) => write!(f, "Invalid storage key: {}", key),
+            StorageError::Io(err) => write!(f, "Storage I/O error: {}", err),
+        }
+    }
+}
+
+impl Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+pub struct StoredObject {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+// Blob store for files the server hosts itself, addressed by slash-separated keys
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn put(&self, key: &str, object: StoredObject) -> StorageResult<()>;
+
+    // Ok(None) if there is no object with the key
+    async fn get(&self, key: &str) -> StorageResult<Option<StoredObject>>;
+
+    async fn delete(&self, key: &str) -> StorageResult<()>;
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StorageKind {
+    Local,
+}
+
+impl FromStr for StorageKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(StorageKind::Local),
+            other => Err(format!("Unknown storage backend: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Storage(Arc<dyn StorageBackend>);
+
+impl Storage {
+    pub fn new(backend: impl StorageBackend + 'static) -> Self {
+        Self(Arc::new(backend))
+    }
+
+    pub fn local(root: PathBuf) -> Self {
+        Self::new(local::LocalStorage::new(root))
+    }
+
+    pub async fn put(&self, key: &str, object: StoredObject) -> StorageResult<()> {
+        validate_key(key)?;
+        self.0.put(key, object).await
+    }
+
+    pub async fn get(&self, key: &str) -> StorageResult<Option<StoredObject>> {
+        validate_key(key)?;
+        self.0.get(key).await
+    }
+
+    pub async fn delete(&self, key: &str) -> StorageResult<()> {
+        validate_key(key)?;
+        self.0.delete(key).await
+    }
+}
+
+// Keys end up in file paths and object names, so only plain relative segments are allowed
+fn validate_key(key: &str) -> StorageResult<()> {
+    let valid = !key.is_empty()
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(StorageError::InvalidKey(key.to_owned()))
+    }
+}